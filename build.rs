@@ -3,6 +3,11 @@ use std::env;
 fn main() {
     let target = env::var("TARGET").unwrap();
 
+    println!("cargo:rustc-check-cfg=cfg(cortex_m)");
+    println!("cargo:rustc-check-cfg=cfg(armv7m)");
+    // set by `cargo fuzz`
+    println!("cargo:rustc-check-cfg=cfg(fuzzing)");
+
     match &target[..] {
         "thumbv6m-none-eabi"
         | "thumbv7m-none-eabi"
@@ -13,4 +18,14 @@ fn main() {
         | "thumbv8m.main-none-eabihf" => println!("cargo:rustc-cfg=cortex_m"),
         _ => {}
     }
+
+    // ARMv7-M and the ARMv8-M Mainline extension (e.g. fault status registers, BASEPRI)
+    match &target[..] {
+        "thumbv7m-none-eabi"
+        | "thumbv7em-none-eabi"
+        | "thumbv7em-none-eabihf"
+        | "thumbv8m.main-none-eabi"
+        | "thumbv8m.main-none-eabihf" => println!("cargo:rustc-cfg=armv7m"),
+        _ => {}
+    }
 }
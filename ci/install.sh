@@ -2,6 +2,10 @@ set -euxo pipefail
 
 main() {
     rustup target add $T
+
+    if [ $T = x86_64-unknown-linux-gnu ] && [ "${TRAVIS_RUST_VERSION-}" = nightly ]; then
+        rustup component add miri rust-src
+    fi
 }
 
 main
@@ -4,6 +4,16 @@ main() {
     case $T in
         x86_64-unknown-linux-gnu)
             cargo test --target $T
+            cargo test --target $T --features async
+            cargo test --target $T --features basepri
+            cargo test --target $T --features panic
+            cargo test --target $T --features defmt
+            cargo test --target $T --features log
+            cargo test --target $T --features defmt,exception_tag
+
+            if [ $TRAVIS_RUST_VERSION = nightly ]; then
+                cargo miri test --target $T --features async
+            fi
         ;;
         thumbv7m-none-eabi)
             cd example
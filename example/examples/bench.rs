@@ -0,0 +1,145 @@
+//! Measures the cycles the table in the "Benchmarks" section of the crate documentation reports
+//!
+//! Priority 1 has a power of 2 sized ring buffer, which is indexed using a mask, and priority 2
+//! has a ring buffer of a size that's not a power of 2, which is indexed using a modulo operation.
+//! Run it on hardware: QEMU doesn't emulate the DWT cycle counter.
+
+#![no_std]
+#![no_main]
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use cortex_m::peripheral::{DWT, NVIC};
+use cortex_m_rt::entry;
+use cortex_m_semihosting::{debug, hprintln};
+use funnel::{funnel, info, Drains, Logger};
+use lm3s6965::{interrupt, Interrupt};
+use panic_halt as _;
+use ufmt::uwriteln;
+
+funnel!(NVIC_PRIO_BITS = 3, {
+    1: 64,
+    2: 48,
+});
+
+// 45 bytes long
+const S: &str = "The quick brown fox jumps over the lazy dog..";
+const N: usize = usize::MAX;
+
+// cycles of `info!("")`, `uwriteln!(logger, "{}", S)` and `uwriteln!(logger, "{}", N)`; row `i` is
+// priority level `i + 1`
+static WRITE: [[AtomicU32; 3]; 2] = [
+    [AtomicU32::new(0), AtomicU32::new(0), AtomicU32::new(0)],
+    [AtomicU32::new(0), AtomicU32::new(0), AtomicU32::new(0)],
+];
+
+#[entry]
+fn main() -> ! {
+    if let Some(mut p) = cortex_m::Peripherals::take() {
+        p.DCB.enable_trace();
+        p.DWT.enable_cycle_counter();
+
+        unsafe {
+            let mut nvic = p.NVIC;
+            nvic.set_priority(Interrupt::GPIOA, 224); // prio = 1
+            nvic.set_priority(Interrupt::GPIOB, 192); // prio = 2
+            NVIC::unmask(Interrupt::GPIOA);
+            NVIC::unmask(Interrupt::GPIOB);
+        }
+    }
+
+    let drains = Drains::take().unwrap();
+    let mut buf = [0; 64];
+
+    // NOTE the drains are sorted highest priority first
+    for drain in drains.iter() {
+        let priority = drain.priority().unwrap_or(0);
+        let row = match priority {
+            1 => 0,
+            2 => 1,
+            _ => continue,
+        };
+
+        let kind = if drain.capacity().is_power_of_two() {
+            "power of 2"
+        } else {
+            "not a power of 2"
+        };
+        hprintln!(
+            "priority {}: {} bytes ({})",
+            priority,
+            drain.capacity(),
+            kind
+        )
+        .ok();
+
+        // one message per run so that `read` always drains a single message
+        for (j, what) in [
+            "info!(\"\")",
+            "uwriteln!(logger, \"{}\", S)",
+            "uwriteln!(logger, \"{}\", N)",
+        ]
+        .iter()
+        .enumerate()
+        {
+            RUN.store(j as u32, Ordering::Relaxed);
+            // GPIOA runs at priority 1 and GPIOB at priority 2
+            NVIC::pend(if row == 0 {
+                Interrupt::GPIOA
+            } else {
+                Interrupt::GPIOB
+            });
+
+            let before = DWT::cycle_count();
+            let n = drain.read(&mut buf).len();
+            let read = DWT::cycle_count().wrapping_sub(before);
+
+            hprintln!(
+                "{}: {} cycles; read({} bytes): {} cycles",
+                what,
+                WRITE[row][j].load(Ordering::Relaxed),
+                n,
+                read
+            )
+            .ok();
+        }
+    }
+
+    debug::exit(debug::EXIT_SUCCESS);
+
+    loop {}
+}
+
+// which of the three messages the interrupt handlers write
+static RUN: AtomicU32 = AtomicU32::new(0);
+
+#[interrupt]
+fn GPIOA() {
+    write(0);
+}
+
+#[interrupt]
+fn GPIOB() {
+    write(1);
+}
+
+fn write(row: usize) {
+    let run = RUN.load(Ordering::Relaxed);
+    let mut logger = Logger::get().unwrap();
+
+    let before = DWT::cycle_count();
+    match run {
+        0 => {
+            info!("").ok();
+        }
+        1 => {
+            uwriteln!(logger, "{}", S).ok();
+        }
+        _ => {
+            uwriteln!(logger, "{}", N).ok();
+        }
+    }
+    let cycles = DWT::cycle_count().wrapping_sub(before);
+
+    WRITE[row][run as usize].store(cycles, Ordering::Relaxed);
+}
@@ -0,0 +1,72 @@
+#![no_std]
+#![no_main]
+
+use cortex_m::peripheral::NVIC;
+use cortex_m_rt::entry;
+use cortex_m_semihosting::{debug, hprintln};
+use funnel::{funnel, info, Drains, Logger};
+use lm3s6965::{interrupt, Interrupt};
+use panic_halt as _;
+
+funnel!(NVIC_PRIO_BITS = 3, {
+    1: 32,
+    2: {
+        log: 32,
+        telemetry: 64,
+    },
+});
+
+funnel::channel!(struct Telemetry = telemetry);
+
+#[entry]
+fn main() -> ! {
+    let drains = Drains::take().unwrap();
+
+    if let Some(p) = cortex_m::Peripherals::take() {
+        unsafe {
+            let mut nvic = p.NVIC;
+            nvic.set_priority(Interrupt::GPIOA, 224); // prio = 1
+            nvic.set_priority(Interrupt::GPIOB, 192); // prio = 2
+            NVIC::unmask(Interrupt::GPIOA);
+            NVIC::unmask(Interrupt::GPIOB);
+        }
+    }
+
+    NVIC::pend(Interrupt::GPIOA);
+    NVIC::pend(Interrupt::GPIOB);
+
+    // text
+    for drain in drains.iter().filter(|drain| drain.channel() == Some("log")) {
+        for byte in drain {
+            hprintln!("{:?} -> {:?}", drain.priority(), byte as char).ok();
+        }
+    }
+
+    // telemetry
+    for drain in drains.channel::<Telemetry>() {
+        for byte in drain {
+            hprintln!("{:?} -> {}", drain.priority(), byte).ok();
+        }
+    }
+
+    debug::exit(debug::EXIT_SUCCESS);
+
+    loop {}
+}
+
+#[interrupt]
+fn GPIOA() {
+    info!("A").ok();
+
+    // no telemetry ring buffer at this priority level
+    assert!(Logger::get_channel::<Telemetry>().is_none());
+}
+
+#[interrupt]
+fn GPIOB() {
+    info!("B").ok();
+
+    if let Some(mut logger) = Logger::get_channel::<Telemetry>() {
+        ufmt::uWrite::write_str(&mut logger, "\x01\x02").ok();
+    }
+}
@@ -0,0 +1,46 @@
+#![no_std]
+#![no_main]
+
+use cortex_m_rt::{entry, exception, ExceptionFrame};
+use cortex_m_semihosting::{debug, hprintln};
+use funnel::{funnel, hardfault::CrashDump, Drains};
+use lm3s6965 as _;
+use panic_halt as _;
+
+funnel!(NVIC_PRIO_BITS = 3, {
+    HardFault: 64,
+    1: 32,
+});
+
+#[entry]
+fn main() -> ! {
+    // validate the ring buffers
+    Drains::take();
+
+    // reading from an unmapped address triggers a bus fault, which escalates into a HardFault
+    unsafe {
+        (0x3FFF_FFF0 as *const u32).read_volatile();
+    }
+
+    loop {}
+}
+
+#[exception]
+fn HardFault(ef: &ExceptionFrame) -> ! {
+    // NOTE `ExceptionFrame` is `#[repr(C)]` and contains eight `u32` fields
+    funnel::hardfault::dump(unsafe { &*(ef as *const ExceptionFrame as *const [u32; 8]) }).ok();
+
+    // NOTE usually the dump would be retrieved after a reset or by the host. `main` never runs
+    // again so we can steal the drains it owns
+    let drains = unsafe { Drains::steal() };
+    if let Some(drain) = drains.hardfault() {
+        let mut buf = [0; funnel::hardfault::SIZE];
+        if let Some(dump) = CrashDump::decode(drain.read(&mut buf)) {
+            hprintln!("{}", dump).ok();
+        }
+    }
+
+    debug::exit(debug::EXIT_SUCCESS);
+
+    loop {}
+}
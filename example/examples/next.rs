@@ -4,7 +4,7 @@
 use cortex_m::peripheral::NVIC;
 use cortex_m_rt::entry;
 use cortex_m_semihosting::{debug, hprintln};
-use funnel::{info, funnel, Drain, Logger};
+use funnel::{info, funnel, Drains, Logger};
 use lm3s6965::{interrupt, Interrupt};
 use panic_halt as _;
 use ufmt::uwrite;
@@ -29,10 +29,10 @@ fn main() -> ! {
     NVIC::pend(Interrupt::GPIOA);
     NVIC::pend(Interrupt::GPIOB);
 
-    let drains = Drain::get_all();
+    let drains = Drains::take().unwrap();
 
     loop {
-        for (i, drain) in drains.iter().cloned().enumerate() {
+        for (i, drain) in drains.iter().enumerate() {
             for byte in drain {
                 hprintln!("{} -> {:?}", i, byte as char).ok();
             }
@@ -0,0 +1,72 @@
+#![no_std]
+#![no_main]
+
+use core::cell::RefCell;
+
+use cortex_m::{interrupt::Mutex, peripheral::NVIC};
+use cortex_m_rt::entry;
+use cortex_m_semihosting::{debug, hprintln};
+use funnel::{funnel, info, Drains};
+use lm3s6965::{interrupt, Interrupt};
+use panic_halt as _;
+
+funnel!(NVIC_PRIO_BITS = 3, notify = Interrupt::GPIOC, {
+    1: 32,
+    2: 64,
+});
+
+// handed to `GPIOC` by `main`
+static DRAINS: Mutex<RefCell<Option<Drains>>> = Mutex::new(RefCell::new(None));
+
+#[entry]
+fn main() -> ! {
+    if let Some(p) = cortex_m::Peripherals::take() {
+        unsafe {
+            let mut nvic = p.NVIC;
+            nvic.set_priority(Interrupt::GPIOA, 224); // prio = 1
+            nvic.set_priority(Interrupt::GPIOB, 192); // prio = 2
+            nvic.set_priority(Interrupt::GPIOC, 255); // lowest priority
+            NVIC::unmask(Interrupt::GPIOA);
+            NVIC::unmask(Interrupt::GPIOB);
+            NVIC::unmask(Interrupt::GPIOC);
+        }
+    }
+
+    cortex_m::interrupt::free(|cs| {
+        *DRAINS.borrow(cs).borrow_mut() = Drains::take();
+
+        NVIC::pend(Interrupt::GPIOA);
+        NVIC::pend(Interrupt::GPIOB);
+    });
+
+    loop {
+        cortex_m::asm::wfi();
+    }
+}
+
+#[interrupt]
+fn GPIOA() {
+    info!("A").ok();
+}
+
+#[interrupt]
+fn GPIOB() {
+    info!("B").ok();
+}
+
+// pended by the loggers
+#[interrupt]
+fn GPIOC() {
+    // NOTE drain outside the critical section so the loggers can preempt this handler
+    let drains = cortex_m::interrupt::free(|cs| DRAINS.borrow(cs).borrow_mut().take());
+    if let Some(drains) = &drains {
+        for (i, drain) in drains.iter().enumerate() {
+            for byte in drain {
+                hprintln!("{} -> {:?}", i, byte as char).ok();
+            }
+        }
+    }
+    cortex_m::interrupt::free(|cs| *DRAINS.borrow(cs).borrow_mut() = drains);
+
+    debug::exit(debug::EXIT_SUCCESS);
+}
@@ -0,0 +1,102 @@
+#![no_std]
+#![no_main]
+
+use cortex_m::peripheral::NVIC;
+use cortex_m_rt::entry;
+use cortex_m_semihosting::{debug, hprintln};
+use funnel::{funnel, info, Config, Drains};
+use lm3s6965::{interrupt, Interrupt};
+use panic_halt as _;
+
+// 3 priority bits: 2 group priority bits (PRIGROUP = 5) and 1 sub-priority bit
+funnel!(
+    NVIC_PRIO_BITS = lm3s6965::NVIC_PRIO_BITS,
+    prigroup = 5,
+    cache = 64,
+    shared = 64,
+    max_ram = 1024,
+    watermark = 75,
+    on_watermark = on_watermark,
+    {
+        #[persistent]
+        1: 32,
+        2: 64,
+    }
+);
+
+// AIRCR writes are ignored unless they include this key
+const VECTKEY: u32 = 0x05FA << 16;
+
+#[entry]
+fn main() -> ! {
+    // validate the ring buffers; this resets the persistent ring buffer after a power cycle
+    let drains = Drains::take().unwrap();
+
+    let mut buf = [0; 32];
+    for drain in drains.iter() {
+        let previous = drain.recover_previous_boot(&mut buf);
+        if !previous.is_empty() {
+            hprintln!("previous boot: {:?}", previous).ok();
+        }
+    }
+
+    let config = Config::get();
+    hprintln!("{} bytes", config.total_bytes).ok();
+
+    if let Some(p) = cortex_m::Peripherals::take() {
+        unsafe {
+            p.SCB.aircr.write(VECTKEY | 5 << 8);
+
+            let mut nvic = p.NVIC;
+            nvic.set_priority(Interrupt::GPIOA, 192); // prio = 1
+            nvic.set_priority(Interrupt::GPIOB, 224); // prio = 1, different sub-priority
+            nvic.set_priority(Interrupt::GPIOC, 128); // prio = 2
+            nvic.set_priority(Interrupt::UART0, 64); // prio = 3, logs into the shared ring buffer
+            NVIC::unmask(Interrupt::GPIOA);
+            NVIC::unmask(Interrupt::GPIOB);
+            NVIC::unmask(Interrupt::GPIOC);
+            NVIC::unmask(Interrupt::UART0);
+        }
+    }
+
+    cortex_m::interrupt::free(|_| {
+        NVIC::pend(Interrupt::GPIOA);
+        NVIC::pend(Interrupt::GPIOB);
+        NVIC::pend(Interrupt::GPIOC);
+        NVIC::pend(Interrupt::UART0);
+    });
+
+    for (i, drain) in drains.iter().chain(drains.shared()).enumerate() {
+        for byte in drain {
+            hprintln!("{} -> {:?}", i, byte as char).ok();
+        }
+    }
+
+    debug::exit(debug::EXIT_SUCCESS);
+
+    loop {}
+}
+
+fn on_watermark(level: usize, capacity: usize) {
+    hprintln!("{}/{} bytes", level, capacity).ok();
+}
+
+#[interrupt]
+fn GPIOA() {
+    info!("A").ok();
+}
+
+#[interrupt]
+fn GPIOB() {
+    info!("B").ok();
+}
+
+#[interrupt]
+fn GPIOC() {
+    info!("C").ok();
+}
+
+#[interrupt]
+fn UART0() {
+    info!("shared").ok();
+}
@@ -4,7 +4,7 @@
 use aligned::Aligned;
 use cortex_m::{itm, peripheral::NVIC};
 use cortex_m_rt::entry;
-use funnel::{funnel, info, Drain};
+use funnel::{funnel, info, Drains};
 use lm3s6965::{interrupt, Interrupt};
 use panic_never as _;
 
@@ -32,11 +32,19 @@ fn main() -> ! {
     NVIC::pend(Interrupt::GPIOA);
     NVIC::pend(Interrupt::GPIOB);
 
-    let drains = Drain::get_all();
+    let drains = if let Some(drains) = Drains::take() {
+        drains
+    } else {
+        // unreachable
+        loop {}
+    };
 
     let mut buf = Aligned([0; 32]);
     loop {
-        for (i, drain) in drains.iter().enumerate() {
+        for drain in drains.iter() {
+            // one stimulus port per priority level
+            let port = usize::from(drain.priority().unwrap_or(0));
+
             'l: loop {
                 let n = drain.read(&mut *buf).len();
                 if n == 0 {
@@ -44,7 +52,7 @@ fn main() -> ! {
                     break 'l;
                 }
                 let buf: &Aligned<_, [_]> = &buf;
-                if let Some(stim) = itm.stim.get_mut(i) {
+                if let Some(stim) = itm.stim.get_mut(port) {
                     itm::write_aligned(stim, &buf[..n]);
                 }
             }
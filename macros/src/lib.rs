@@ -1,6 +1,6 @@
 extern crate proc_macro;
 
-use core::{fmt::Display, ops::RangeInclusive, str::FromStr};
+use core::{cmp, fmt::Display, ops::RangeInclusive, str::FromStr};
 use proc_macro::TokenStream;
 use std::collections::BTreeMap;
 
@@ -11,7 +11,8 @@ use syn::{
     parse::{self, Parse, ParseStream},
     parse_macro_input,
     punctuated::Punctuated,
-    token, Ident, LitInt, Path, Token,
+    token, Attribute, Expr, ExprLit, ExprPath, Ident, Lit, LitInt, LitStr, Meta, MetaNameValue,
+    Path, Token,
 };
 
 #[proc_macro]
@@ -32,92 +33,606 @@ fn main(input: Input) -> parse::Result<TokenStream> {
         ));
     }
 
-    let (bits, upper) = match input.bits {
+    let bits = match input.bits {
+        Either::Left(bits) => Either::Left(lit2ux::<u8>(&bits, Some(1..=8))?),
+        Either::Right(path) => Either::Right(path),
+    };
+
+    let mut cache = None;
+    let mut link_section = None;
+    let mut max_ram = None;
+    let mut notify = None;
+    let mut power_of_two = None;
+    let mut prigroup = None;
+    let mut shared = None;
+    let mut watermark = None;
+    let mut on_watermark = None;
+    for opt in &input.options {
+        let key = opt.key.to_string();
+        let slot = match &*key {
+            "cache" => &mut cache,
+            "link_section" => &mut link_section,
+            "max_ram" => &mut max_ram,
+            "notify" => &mut notify,
+            "on_watermark" => &mut on_watermark,
+            "power_of_two" => &mut power_of_two,
+            "prigroup" => &mut prigroup,
+            "shared" => &mut shared,
+            "watermark" => &mut watermark,
+            _ => {
+                return Err(parse::Error::new(
+                    opt.key.span(),
+                    format!("unknown option `{}`", key),
+                ));
+            }
+        };
+
+        if slot.is_some() {
+            return Err(parse::Error::new(
+                opt.key.span(),
+                "option appears more than once",
+            ));
+        }
+
+        *slot = Some(opt);
+    }
+
+    let watermark = match (watermark, on_watermark) {
+        (Some(watermark), Some(on_watermark)) => {
+            let percent: usize = lit2ux(expr2lit(&watermark.value)?, Some(1..=100))?;
+            Some((percent, &on_watermark.value))
+        }
+
+        (None, None) => None,
+
+        (Some(opt), None) | (None, Some(opt)) => {
+            return Err(parse::Error::new(
+                opt.key.span(),
+                "the `watermark` and `on_watermark` options must be used together",
+            ));
+        }
+    };
+
+    let power_of_two = if let Some(opt) = power_of_two {
+        expr2bool(&opt.value)?
+    } else {
+        false
+    };
+
+    let link_section = if let Some(opt) = link_section {
+        Some(expr2str(&opt.value)?)
+    } else {
+        None
+    };
+
+    // NOTE with PRIGROUP = 7 there are no group priority bits and interrupts can't preempt each
+    // other
+    let prigroup: u8 = if let Some(opt) = prigroup {
+        lit2ux(expr2lit(&opt.value)?, Some(0..=6))?
+    } else {
+        0
+    };
+
+    // the number of group (preemption) priority bits; the bits below them hold the sub-priority,
+    // which doesn't affect preemption so it doesn't select a logger either. With a path the
+    // number is computed by the compiler; see `__FUNNEL_G`
+    let (group_bits, upper) = match bits {
         Either::Left(bits) => {
-            let bits = lit2ux::<u8>(&bits, Some(1..=8))?;
-            (Either::Left(bits), 1 << bits)
+            let group_bits = cmp::min(bits, 7 - prigroup);
+            (Either::Left(group_bits), 1 << group_bits)
         }
 
-        Either::Right(path) => {
-            (Either::Right(path), 255)
+        Either::Right(_) => (Either::Right(quote!(__FUNNEL_G)), 255),
+    };
+
+    let shared = if let Some(opt) = shared {
+        let size = expr2lit(&opt.value)?;
+        let v = Buffer {
+            size: lit2ux(size, Some(1..=usize::MAX))?,
+            link_section: link_section.clone(),
+        };
+
+        if power_of_two && !v.size.is_power_of_two() {
+            return Err(parse::Error::new(
+                size.span(),
+                "size must be a power of 2 (`power_of_two = true`)",
+            ));
         }
+
+        Some(v)
+    } else {
+        None
     };
 
     let mut map = BTreeMap::new();
+    let mut hardfault = None;
+    // the channel names, by their uppercase version, which names the generated items
+    let mut names = BTreeMap::<String, String>::new();
     for kv in &input.map {
-        let k = lit2ux(&kv.priority, Some(1..=upper))?;
-        let v: usize = lit2ux(&kv.size, Some(1..=usize::max_value()))?;
+        // `$priority: $size` is a shorthand for `$priority: { log: $size }`
+        let channels = match &kv.value {
+            Value::Size(size) => vec![(None, size, vec![])],
+            Value::Channels { channels, .. } => channels
+                .iter()
+                .map(|channel| {
+                    (
+                        Some(&channel.name),
+                        &channel.size,
+                        channel.attrs.iter().collect(),
+                    )
+                })
+                .collect(),
+        };
 
-        if map.contains_key(&k) {
-            return Err(parse::Error::new(
-                kv.priority.span(),
-                "priority appears more than once",
-            ));
+        let mut buffers: Vec<(String, Buffer)> = vec![];
+        for (name, size, attrs) in channels {
+            let mut v = Buffer {
+                size: lit2ux(size, Some(1..=usize::MAX))?,
+                link_section: link_section.clone(),
+            };
+
+            if power_of_two && !v.size.is_power_of_two() {
+                return Err(parse::Error::new(
+                    size.span(),
+                    "size must be a power of 2 (`power_of_two = true`)",
+                ));
+            }
+
+            // NOTE the attributes of a channel take precedence over the attributes of its entry
+            for attr in kv.attrs.iter().chain(attrs) {
+                apply(attr, &mut v)?;
+            }
+
+            let span = name.map(|name| name.span()).unwrap_or_else(|| size.span());
+            let name = name
+                .map(|name| name.to_string())
+                .unwrap_or_else(|| "log".to_string());
+            if buffers.iter().any(|(other, _)| *other == name) {
+                return Err(parse::Error::new(
+                    size.span(),
+                    format!("channel `{}` appears more than once", name),
+                ));
+            }
+
+            let other = names
+                .entry(name.to_uppercase())
+                .or_insert_with(|| name.clone());
+            if *other != name {
+                return Err(parse::Error::new(
+                    span,
+                    format!(
+                        "channel `{}` differs from channel `{}` only in case; channel names must \
+                         be unique regardless of case",
+                        name, other
+                    ),
+                ));
+            }
+
+            buffers.push((name, v));
+        }
+
+        if let Value::Channels { brace, .. } = &kv.value {
+            if buffers.is_empty() {
+                return Err(parse::Error::new(
+                    brace.span,
+                    "expected at least one channel",
+                ));
+            }
         }
 
-        map.insert(k, v);
+        let k = match &kv.priority {
+            Either::Left(priority) => {
+                let k = lit2ux(priority, Some(1..=upper))?;
+
+                if map.contains_key(&k) {
+                    return Err(parse::Error::new(
+                        priority.span(),
+                        "priority appears more than once",
+                    ));
+                }
+
+                k
+            }
+
+            Either::Right(ident) => {
+                if ident != "HardFault" {
+                    return Err(parse::Error::new(
+                        ident.span(),
+                        "expected a priority or `HardFault`",
+                    ));
+                }
+
+                if hardfault.is_some() {
+                    return Err(parse::Error::new(
+                        ident.span(),
+                        "`HardFault` appears more than once",
+                    ));
+                }
+
+                if let Value::Channels { .. } = kv.value {
+                    return Err(parse::Error::new(
+                        ident.span(),
+                        "the `HardFault` ring buffer doesn't support channels",
+                    ));
+                }
+
+                hardfault = buffers.pop().map(|(_, v)| v);
+                continue;
+            }
+        };
+
+        map.insert(k, buffers);
+    }
+
+    let max_ram = if let Some(opt) = max_ram {
+        let max_ram: usize = lit2ux(expr2lit(&opt.value)?, None)?;
+        Some(quote!(
+            const _: () = assert!(
+                __FUNNEL_TOTAL <= #max_ram,
+                "the `funnel!` ring buffers use more RAM than allowed by `max_ram`"
+            );
+        ))
+    } else {
+        None
+    };
+
+    // NOTE `__FUNNEL_D` is sorted by priority, highest priority first; the channels of a priority
+    // level appear in declaration order
+    let d = map
+        .iter()
+        .rev()
+        .flat_map(|(prio, buffers)| buffers.iter().map(move |(name, v)| (*prio, name, v)))
+        .collect::<Vec<_>>();
+
+    // NOTE two index values are reserved: `u8::MAX` means "no logger" in the lookup tables and
+    // `funnel::UNRESOLVED` (`0xFE`) marks the entries of the `cache` that have not been resolved
+    // yet. The indices into `__FUNNEL_D` go from `0` to `n - 1` so `n` can be at most `0xFE`
+    let n = d.len();
+    if n >= usize::from(u8::MAX) {
+        return Err(parse::Error::new(
+            Span::call_site(),
+            "`funnel!` supports at most 254 ring buffers",
+        ));
     }
 
     let mut loggers = vec![];
     let mut ls = vec![];
-    let mut ifs = vec![];
-    for (prio, size) in &map {
-        let l = logger_ident(*prio);
+    let mut consts = vec![];
+    // the `__FUNNEL_T` entries of each channel
+    let mut entries = BTreeMap::<&str, Vec<_>>::new();
+    let mut buffers = vec![];
+    let mut sizes = vec![];
+    for (index, (prio, name, Buffer { size, link_section })) in d.iter().enumerate() {
+        let l = logger_ident(*prio, name);
 
-        loggers
-            .push(quote!(static #l: funnel::Inner<[u8; #size]> = funnel::Inner::new([0; #size]);));
-        let (const_, nvic_prio) = match bits {
-            Either::Left(bits) => {
-                let nvic_prio = ((1 << bits) - prio) << (8 - bits);
-                (None, quote!(#nvic_prio))
+        let link_section = link_section
+            .as_ref()
+            .map(|section| quote!(#[link_section = #section]));
+        loggers.push(quote!(
+            #link_section
+            static #l: funnel::Inner<[u8; #size]> = funnel::Inner::new([0; #size]);
+        ));
+        let nvic_prio = match group_bits {
+            Either::Left(group_bits) => {
+                let nvic_prio = ((1 << group_bits) - prio) << (8 - group_bits);
+                quote!(#nvic_prio)
             }
 
-            Either::Right(ref path) => {
+            Either::Right(ref g) => {
                 let px = priority_ident(*prio);
-                let const_ = quote!(const #px: u8 = ((1 << #path) - #prio) << (8 - #path););
-                (Some(const_), quote!(#px))
+                if !consts.iter().any(|(p, _)| p == prio) {
+                    consts.push((
+                        *prio,
+                        quote!(const #px: u8 = ((1 << #g) - #prio) << (8 - #g);),
+                    ));
+                }
+                quote!(#px)
             }
         };
 
-        ifs.push(quote!(
-            #const_
-            if nvic_prio == #nvic_prio {
-                return Some(&#l);
+        let index = index as u8;
+        entries
+            .entry(name)
+            .or_default()
+            .push(quote!(t[(1 << __FUNNEL_G) - #prio as usize] = #index;));
+
+        let bytes = quote!(core::mem::size_of::<funnel::Inner<[u8; #size]>>());
+        buffers.push(quote!(
+            funnel::Buffer {
+                priority: #prio,
+                nvic_priority: #nvic_prio,
+                channel: #name,
+                size: #size,
+                bytes: #bytes,
             }
         ));
+        sizes.push(bytes);
 
         ls.push(l);
     }
+    let consts = consts.into_iter().map(|(_, c)| c);
+    let is = 0..ls.len();
+
+    // maps `nvic_prio >> (8 - __FUNNEL_G)` to an index into `__FUNNEL_D`; `u8::MAX` means no
+    // logger
+    let table = |entries: &[proc_macro2::TokenStream]| {
+        quote!(
+            {
+                #[allow(unused_mut)]
+                let mut t = [u8::MAX; 1 << __FUNNEL_G];
+                #(#entries)*
+                t
+            }
+        )
+    };
+    let log_table = table(entries.remove("log").as_deref().unwrap_or(&[]));
+    let channels = entries.iter().map(|(name, entries)| {
+        let t = Ident::new(
+            &format!("__FUNNEL_T_{}", name.to_uppercase()),
+            Span::call_site(),
+        );
+        let f = Ident::new(&format!("__funnel_channel_{}", name), Span::call_site());
+        let table = table(entries);
+
+        quote!(
+            static #t: [u8; 1 << __FUNNEL_G] = #table;
+
+            #[no_mangle]
+            fn #f(nvic_prio: u8) -> u8 {
+                #t.get((nvic_prio >> (8 - __FUNNEL_G)) as usize)
+                    .cloned()
+                    .unwrap_or(u8::MAX)
+            }
+        )
+    });
+
+    // NOTE the HardFault ring buffer is not part of `__FUNNEL_D` or `__FUNNEL_C.buffers` because it
+    // has no configurable priority; it's only accessible through `Drain::hardfault`
+    let hardfault = if let Some(Buffer { size, link_section }) = hardfault {
+        let link_section = link_section
+            .as_ref()
+            .map(|section| quote!(#[link_section = #section]));
+        loggers.push(quote!(
+            #link_section
+            static __FUNNEL_HF: funnel::Inner<[u8; #size]> = funnel::Inner::new([0; #size]);
+        ));
+        sizes.push(quote!(core::mem::size_of::<funnel::Inner<[u8; #size]>>()));
+
+        quote!(Some(unsafe { funnel::Ring::new(&__FUNNEL_HF, None) }))
+    } else {
+        quote!(None)
+    };
+
+    // NOTE the shared ring buffer is not part of `__FUNNEL_D` or `__FUNNEL_C.buffers` either; any
+    // priority level that has no ring buffer of its own writes into it
+    let shared_used = shared.is_some();
+    let shared = if let Some(Buffer { size, link_section }) = shared {
+        let link_section = link_section
+            .as_ref()
+            .map(|section| quote!(#[link_section = #section]));
+        loggers.push(quote!(
+            #link_section
+            static __FUNNEL_S: funnel::Shared<[u8; #size]> = funnel::Shared::new([0; #size]);
+        ));
+        sizes.push(quote!(core::mem::size_of::<funnel::Shared<[u8; #size]>>()));
+
+        quote!(Some(funnel::Ring::shared(&__FUNNEL_S)))
+    } else {
+        quote!(None)
+    };
+
+    // NOTE the code that writes into the shared ring buffer is only linked in when the `shared`
+    // option is used
+    let (get, append) = if shared_used {
+        (
+            quote!(funnel::Logger::get_or_shared(funnel::Ring::shared(
+                &__FUNNEL_S
+            ))),
+            quote!(logger.append_shared(input)),
+        )
+    } else {
+        (
+            quote!(funnel::Logger::get_exclusive()),
+            quote!(logger.append(input)),
+        )
+    };
+
+    let nvic_prio_bits = match bits {
+        Either::Left(bits) => quote!(#bits),
+        Either::Right(ref path) => quote!(#path),
+    };
+
+    let on_watermark = watermark.map(|(percent, f)| {
+        quote!(
+            let capacity = logger.capacity();
+            if funnel::crossed_watermark(before, after, capacity, #percent) {
+                #f(after, capacity);
+            }
+        )
+    });
+    let notify = notify.map(|opt| match &opt.value {
+        Expr::Path(ExprPath { path, .. }) if path.is_ident("PendSV") => {
+            quote!(funnel::Notify::PendSV)
+        }
+        interrupt => quote!(funnel::Notify::Interrupt(#interrupt as u16)),
+    });
+    // exception number of the interrupt the drains may run in (`0` = thread mode)
+    let notify_exception = notify
+        .as_ref()
+        .map(|notify| quote!(#notify.exception()))
+        .unwrap_or_else(|| quote!(0));
+    let notify = notify.map(|notify| quote!(#notify.pend();));
+
+    let cache = if let Some(opt) = cache {
+        let len = &opt.value;
+        quote!(
+            static __FUNNEL_K: [core::sync::atomic::AtomicU8; #len] = {
+                const __FUNNEL_U: core::sync::atomic::AtomicU8 =
+                    core::sync::atomic::AtomicU8::new(funnel::UNRESOLVED);
+
+                [__FUNNEL_U; #len]
+            };
+
+            &__FUNNEL_K
+        )
+    } else {
+        quote!(&[])
+    };
 
-    ls.reverse();
-    let n = map.len();
     Ok(quote!(
         const FUNNEL: () = {
+            #(#consts)*
             #(#loggers)*
-            static D: [&'static funnel::Inner<[u8]>; #n] = [#(&#ls),*];
+            // the description of each ring buffer of `__FUNNEL_D`, in the same order
+            static __FUNNEL_B: [funnel::Buffer; #n] = [#(#buffers),*];
+
+            // NOTE(unsafe) each ring buffer gets a single `Ring`
+            static __FUNNEL_D: [funnel::Ring; #n] =
+                unsafe { [#(funnel::Ring::new(&#ls, Some(&__FUNNEL_B[#is]))),*] };
+
+            const __FUNNEL_TOTAL: usize = 0 #(+ #sizes)*;
+            #max_ram
+
+            static __FUNNEL_C: funnel::Config = funnel::Config {
+                nvic_prio_bits: __FUNNEL_BITS,
+                prigroup: #prigroup,
+                buffers: &__FUNNEL_B,
+                total_bytes: __FUNNEL_TOTAL,
+            };
+
+            const __FUNNEL_BITS: u8 = #nvic_prio_bits;
+
+            // number of group priority bits
+            const __FUNNEL_G: u8 = if __FUNNEL_BITS < 7 - #prigroup {
+                __FUNNEL_BITS
+            } else {
+                7 - #prigroup
+            };
+
+            // the `log` channel
+            static __FUNNEL_T: [u8; 1 << __FUNNEL_G] = #log_table;
+
+            #(#channels)*
+
+            // the `defmt` frame being written into each ring buffer of `__FUNNEL_D` plus the
+            // HardFault ring buffer (the last element); these are zero sized unless the "defmt"
+            // feature is enabled
+            static __FUNNEL_F: [funnel::Frame; #n + 1] = {
+                const __FUNNEL_E: funnel::Frame = funnel::Frame::new();
+
+                [__FUNNEL_E; #n + 1]
+            };
+
+            #[no_mangle]
+            fn __funnel_index(nvic_prio: u8) -> u8 {
+                __FUNNEL_T
+                    .get((nvic_prio >> (8 - __FUNNEL_G)) as usize)
+                    .cloned()
+                    .unwrap_or(u8::MAX)
+            }
+
+            #[no_mangle]
+            fn __funnel_cache() -> &'static [core::sync::atomic::AtomicU8] {
+                #cache
+            }
 
             #[no_mangle]
-            fn __funnel_logger(nvic_prio: u8) -> Option<&'static funnel::Inner<[u8]>> {
-                #(#ifs)*
+            fn __funnel_hardfault() -> Option<funnel::Ring> {
+                #hardfault
+            }
 
-                None
+            #[no_mangle]
+            fn __funnel_shared() -> Option<funnel::Ring> {
+                #shared
             }
 
             #[no_mangle]
-            fn __funnel_drains() -> &'static [&'static funnel::Inner<[u8]>] {
-                &D
+            fn __funnel_get() -> Option<funnel::Logger> {
+                unsafe { #get }
+            }
+
+            #[no_mangle]
+            fn __funnel_drains() -> &'static [funnel::Ring] {
+                &__FUNNEL_D
+            }
+
+            #[no_mangle]
+            fn __funnel_frames() -> &'static [funnel::Frame] {
+                &__FUNNEL_F
+            }
+
+            #[no_mangle]
+            fn __funnel_config() -> &'static funnel::Config {
+                &__FUNNEL_C
+            }
+
+            #[no_mangle]
+            fn __funnel_notify_exception() -> u16 {
+                #notify_exception
+            }
+
+            // NOTE only the hooks of the options that were used are called after a write
+            #[allow(unused_variables)]
+            #[no_mangle]
+            fn __funnel_log(logger: &funnel::Logger, input: &[u8]) -> Result<(), ()> {
+                let (before, after) = #append.ok_or(())?;
+                #on_watermark
+                #notify
+                Ok(())
             }
         };
     )
     .into())
 }
 
-fn logger_ident(prio: u8) -> Ident {
-    Ident::new(&format!("L{}", prio), Span::call_site())
+struct Buffer {
+    size: usize,
+    link_section: Option<LitStr>,
+}
+
+fn logger_ident(prio: u8, channel: &str) -> Ident {
+    if channel == "log" {
+        Ident::new(&format!("__FUNNEL_L{}", prio), Span::call_site())
+    } else {
+        Ident::new(
+            &format!("__FUNNEL_L{}_{}", prio, channel.to_uppercase()),
+            Span::call_site(),
+        )
+    }
+}
+
+// Applies a `#[link_section]` or `#[persistent]` attribute to a ring buffer
+fn apply(attr: &Attribute, v: &mut Buffer) -> parse::Result<()> {
+    if attr.path.is_ident("link_section") {
+        match attr.parse_meta()? {
+            Meta::NameValue(MetaNameValue {
+                lit: Lit::Str(section),
+                ..
+            }) => v.link_section = Some(section),
+
+            _ => {
+                return Err(parse::Error::new_spanned(
+                    attr,
+                    "expected `#[link_section = \"$section\"]`",
+                ))
+            }
+        }
+    } else if attr.path.is_ident("persistent") {
+        if !attr.tokens.is_empty() {
+            return Err(parse::Error::new_spanned(attr, "expected `#[persistent]`"));
+        }
+
+        // `cortex-m-rt` doesn't initialize this section at boot
+        v.link_section = Some(LitStr::new(".uninit.funnel", Span::call_site()));
+    } else {
+        return Err(parse::Error::new_spanned(attr, "unsupported attribute"));
+    }
+
+    Ok(())
 }
 
 fn priority_ident(prio: u8) -> Ident {
-    Ident::new(&format!("P{}", prio), Span::call_site())
+    Ident::new(&format!("__FUNNEL_P{}", prio), Span::call_site())
 }
 
 fn lit2ux<T>(lit: &LitInt, range: Option<RangeInclusive<T>>) -> parse::Result<T>
@@ -146,6 +661,43 @@ where
     Ok(n)
 }
 
+fn expr2lit(expr: &Expr) -> parse::Result<&LitInt> {
+    match expr {
+        Expr::Lit(ExprLit {
+            lit: Lit::Int(lit), ..
+        }) => Ok(lit),
+
+        _ => Err(parse::Error::new_spanned(
+            expr,
+            "expected an integer literal",
+        )),
+    }
+}
+
+fn expr2bool(expr: &Expr) -> parse::Result<bool> {
+    match expr {
+        Expr::Lit(ExprLit {
+            lit: Lit::Bool(lit),
+            ..
+        }) => Ok(lit.value),
+
+        _ => Err(parse::Error::new_spanned(
+            expr,
+            "expected `true` or `false`",
+        )),
+    }
+}
+
+fn expr2str(expr: &Expr) -> parse::Result<LitStr> {
+    match expr {
+        Expr::Lit(ExprLit {
+            lit: Lit::Str(lit), ..
+        }) => Ok(lit.clone()),
+
+        _ => Err(parse::Error::new_spanned(expr, "expected a string literal")),
+    }
+}
+
 enum Either<A, B> {
     Left(A),
     Right(B),
@@ -168,6 +720,7 @@ struct Input {
     _eq: Token![=],
     bits: Either<LitInt, Path>,
     _comma: Token![,],
+    options: Vec<Opt>,
     _brace: token::Brace,
     map: Punctuated<KeyValue, Token![,]>,
 }
@@ -180,22 +733,88 @@ impl Parse for Input {
             _eq: input.parse()?,
             bits: parse_either(input)?,
             _comma: input.parse()?,
+            options: {
+                let mut options = vec![];
+                while !input.peek(token::Brace) {
+                    options.push(input.parse()?);
+                    let _: Token![,] = input.parse()?;
+                }
+                options
+            },
             _brace: braced!(content in input),
             map: Punctuated::parse_terminated(&content)?,
         })
     }
 }
 
+struct Opt {
+    key: Ident,
+    _eq: Token![=],
+    value: Expr,
+}
+
+impl Parse for Opt {
+    fn parse(input: ParseStream) -> parse::Result<Self> {
+        Ok(Self {
+            key: input.parse()?,
+            _eq: input.parse()?,
+            value: input.parse()?,
+        })
+    }
+}
+
 struct KeyValue {
-    priority: LitInt,
+    attrs: Vec<Attribute>,
+    priority: Either<LitInt, Ident>,
     _colon: Token![:],
-    size: LitInt,
+    value: Value,
 }
 
 impl Parse for KeyValue {
     fn parse(input: ParseStream) -> parse::Result<Self> {
         Ok(Self {
-            priority: input.parse()?,
+            attrs: input.call(Attribute::parse_outer)?,
+            priority: parse_either(input)?,
+            _colon: input.parse()?,
+            value: input.parse()?,
+        })
+    }
+}
+
+enum Value {
+    Size(LitInt),
+    Channels {
+        brace: token::Brace,
+        channels: Punctuated<Channel, Token![,]>,
+    },
+}
+
+impl Parse for Value {
+    fn parse(input: ParseStream) -> parse::Result<Self> {
+        if input.peek(token::Brace) {
+            let content;
+            Ok(Value::Channels {
+                brace: braced!(content in input),
+                channels: Punctuated::parse_terminated(&content)?,
+            })
+        } else {
+            Ok(Value::Size(input.parse()?))
+        }
+    }
+}
+
+struct Channel {
+    attrs: Vec<Attribute>,
+    name: Ident,
+    _colon: Token![:],
+    size: LitInt,
+}
+
+impl Parse for Channel {
+    fn parse(input: ParseStream) -> parse::Result<Self> {
+        Ok(Self {
+            attrs: input.call(Attribute::parse_outer)?,
+            name: input.parse()?,
             _colon: input.parse()?,
             size: input.parse()?,
         })
@@ -0,0 +1,201 @@
+//! A `defmt` global logger
+//!
+//! Enabling the "defmt" Cargo feature makes `funnel` the `#[defmt::global_logger]`. Each `defmt`
+//! frame is encoded into the ring buffer of the caller's priority level so, unlike transports that
+//! take a critical section for each frame, logging from an interrupt handler never masks
+//! interrupts. Frames logged from a priority level that has no ring buffer are dropped.
+//!
+//! Frames are never interleaved within a ring buffer: a frame is written in full before the
+//! interrupt handler that logs it returns, and only higher priority handlers, which log into
+//! different ring buffers, can preempt it. The drains can then forward the ring buffers to any
+//! transport (e.g. the ITM or an UART) as long as each ring buffer is read until it's empty, so
+//! frames from different ring buffers are not mixed.
+//!
+//! ``` ignore
+//! funnel!(NVIC_PRIO_BITS = 3, {
+//!     1: 128,
+//!     2: 256,
+//! });
+//!
+//! #[interrupt]
+//! fn GPIOA() {
+//!     defmt::info!("GPIOA {}", 42);
+//! }
+//! ```
+//!
+//! When a frame doesn't fit in the ring buffer the rest of the frame is dropped; with the default
+//! `rzcobs` encoding the host decoder discards the incomplete frame and recovers at the next one.
+//!
+//! With the "exception_tag" feature enabled each frame starts with one extra byte, the number of
+//! the exception that logged it truncated to 8 bits (`0` = thread mode). The byte is encoded
+//! together with the frame so the host must decode the frame, strip its first byte and then pass
+//! the rest to the `defmt` decoder.
+
+use core::ptr;
+
+use ::defmt::Encoder;
+
+use crate::Logger;
+
+// Frames are encoded in chunks of this many bytes
+const CHUNK: usize = 32;
+
+// The `rzcobs` encoding adds, at most, one byte per 7 bytes of input
+const ENCODED_CHUNK: usize = CHUNK + CHUNK / 7 + 1;
+
+pub(crate) struct State {
+    encoder: Encoder,
+    // part of the current frame didn't fit in the ring buffer
+    truncated: bool,
+}
+
+impl State {
+    pub(crate) const fn new() -> Self {
+        State {
+            encoder: Encoder::new(),
+            truncated: false,
+        }
+    }
+
+    pub(crate) fn start(&mut self, logger: &Logger) {
+        self.truncated = false;
+
+        let mut buf = Buffer::new();
+        self.encoder.start_frame(|bytes| buf.push(bytes));
+        // NOTE the tag is encoded like the rest of the frame so it can't be mistaken for a
+        // delimiter
+        #[cfg(feature = "exception_tag")]
+        self.encoder
+            .write(&[crate::exception() as u8], |bytes| buf.push(bytes));
+        self.emit(logger, &buf);
+    }
+
+    pub(crate) fn write(&mut self, logger: &Logger, bytes: &[u8]) {
+        for chunk in bytes.chunks(CHUNK) {
+            let mut buf = Buffer::new();
+            self.encoder.write(chunk, |bytes| buf.push(bytes));
+            self.emit(logger, &buf);
+        }
+    }
+
+    pub(crate) fn end(&mut self, logger: &Logger) {
+        let mut buf = Buffer::new();
+        self.encoder.end_frame(|bytes| buf.push(bytes));
+
+        if self.truncated || buf.truncated {
+            // only terminate the incomplete frame so the decoder can find the start of the next
+            // one; `rzcobs` frames end with a `0` byte
+            logger.log(&[0]).ok();
+        } else {
+            self.emit(logger, &buf);
+        }
+    }
+
+    // Writes `buf` into the ring buffer unless part of the current frame was already dropped
+    fn emit(&mut self, logger: &Logger, buf: &Buffer) {
+        if buf.truncated {
+            self.truncated = true;
+        }
+
+        let bytes = buf.as_slice();
+        if !self.truncated && !bytes.is_empty() && logger.log(bytes).is_err() {
+            self.truncated = true;
+        }
+    }
+}
+
+// NOTE the `Encoder` hands over the encoded data a few bytes at a time; we collect it to perform
+// a single write into the ring buffer
+pub(crate) struct Buffer {
+    bytes: [u8; ENCODED_CHUNK],
+    len: usize,
+    // some of the data didn't fit
+    pub(crate) truncated: bool,
+}
+
+impl Buffer {
+    pub(crate) fn new() -> Self {
+        Buffer {
+            bytes: [0; ENCODED_CHUNK],
+            len: 0,
+            truncated: false,
+        }
+    }
+
+    // NOTE the `Encoder` emits at most `ENCODED_CHUNK` bytes per chunk of input (or per frame
+    // delimiter) so the data always fits. Still, we check it: an encoder that emits more would
+    // otherwise make us write past the end of `bytes`
+    pub(crate) fn push(&mut self, bytes: &[u8]) {
+        if self.truncated || bytes.len() > ENCODED_CHUNK - self.len {
+            self.truncated = true;
+            return;
+        }
+
+        // NOTE we use `ptr::copy_nonoverlapping` instead of `copy_from_slice` to avoid panicking
+        // branches
+        unsafe {
+            // self.bytes[self.len..self.len + bytes.len()].copy_from_slice(bytes);
+            ptr::copy_nonoverlapping(
+                bytes.as_ptr(),
+                self.bytes.as_mut_ptr().add(self.len),
+                bytes.len(),
+            );
+        }
+        self.len += bytes.len();
+    }
+
+    pub(crate) fn as_slice(&self) -> &[u8] {
+        // &self.bytes[..self.len]
+        unsafe { self.bytes.get_unchecked(..self.len) }
+    }
+}
+
+#[::defmt::global_logger]
+struct Funnel;
+
+unsafe impl ::defmt::Logger for Funnel {
+    fn acquire() {
+        if let Some((logger, state)) = current() {
+            state.start(&logger);
+        }
+    }
+
+    unsafe fn flush() {
+        // NOTE `funnel` doesn't drain the ring buffers itself; the application does, from one of the
+        // contexts `Drain::read` accepts (thread mode, the `notify` interrupt or HardFault)
+    }
+
+    unsafe fn release() {
+        if let Some((logger, state)) = current() {
+            state.end(&logger);
+        }
+    }
+
+    unsafe fn write(bytes: &[u8]) {
+        if let Some((logger, state)) = current() {
+            state.write(&logger, bytes);
+        }
+    }
+}
+
+// Returns the logger of the caller's priority level and the state of the frame it's writing
+fn current() -> Option<(Logger, &'static mut State)> {
+    #[cfg(cortex_m)]
+    {
+        extern "Rust" {
+            // NOTE the expansion of `funnel!` always defines this function; it has one more
+            // element than `__funnel_drains`, for the HardFault ring buffer
+            fn __funnel_frames() -> &'static [crate::Frame];
+        }
+
+        let (logger, index) = Logger::resolve()?;
+        let frame = unsafe { __funnel_frames() }.get(index)?;
+
+        // NOTE only this priority level accesses this frame and, within a priority level, `defmt`
+        // doesn't nest frames
+        Some((logger, unsafe { &mut *frame.state.get() }))
+    }
+
+    #[cfg(not(cortex_m))]
+    None
+}
@@ -0,0 +1,241 @@
+//! HardFault crash dumps
+//!
+//! `dump` writes the stacked exception frame and the fault status registers into the `HardFault`
+//! ring buffer, which is declared using a `HardFault` entry in the `funnel!` macro. `CrashDump`
+//! decodes the dump, e.g. on the host, and formats it as a crash report.
+//!
+//! # Format
+//!
+//! A dump is `SIZE` bytes long. All the words are stored in little endian order.
+//!
+//! | Offset | Size | Contents                                 |
+//! |--------|------|------------------------------------------|
+//! | 0      | 2    | `b"HF"`                                  |
+//! | 2      | 1    | format version (`VERSION`)               |
+//! | 3      | 1    | number of words that follow (`12`)       |
+//! | 4      | 32   | r0, r1, r2, r3, r12, lr, pc, xpsr        |
+//! | 36     | 16   | CFSR, HFSR, MMFAR, BFAR                  |
+//!
+//! The fault status registers don't exist on ARMv6-M and ARMv8-M Baseline; on those devices they
+//! are reported as `0`.
+
+use core::fmt;
+
+use crate::Logger;
+
+// Cortex-M MMIO registers
+#[cfg(armv7m)]
+const SCB_CFSR: *const u32 = 0xE000_ED28 as *const u32;
+#[cfg(armv7m)]
+const SCB_HFSR: *const u32 = 0xE000_ED2C as *const u32;
+#[cfg(armv7m)]
+const SCB_MMFAR: *const u32 = 0xE000_ED34 as *const u32;
+#[cfg(armv7m)]
+const SCB_BFAR: *const u32 = 0xE000_ED38 as *const u32;
+
+/// Version of the dump format
+pub const VERSION: u8 = 1;
+
+/// Size of a dump, in bytes
+pub const SIZE: usize = 4 + 4 * WORDS;
+
+const WORDS: usize = 12;
+
+/// Writes the stacked exception `frame` (r0, r1, r2, r3, r12, lr, pc, xpsr) plus the fault status
+/// registers into the `HardFault` ring buffer
+///
+/// This returns an error if called outside the `HardFault` handler, if no `HardFault` ring buffer
+/// was declared or if the ring buffer doesn't have room for the dump.
+// NOTE the error carries no information, like the `uWrite` error of `Logger`
+#[allow(clippy::result_unit_err)]
+pub fn dump(frame: &[u32; 8]) -> Result<(), ()> {
+    // NOTE not `Logger::get`, which falls back to the shared ring buffer when there's no
+    // `HardFault` ring buffer
+    let (logger, _) = Logger::resolve().ok_or(())?;
+
+    #[cfg(cortex_m)]
+    {
+        if crate::exception() != 3 {
+            // not in the HardFault handler
+            return Err(());
+        }
+    }
+
+    logger.log(&encode(frame, status()))
+}
+
+// CFSR, HFSR, MMFAR, BFAR
+fn status() -> [u32; 4] {
+    #[cfg(armv7m)]
+    unsafe {
+        [
+            SCB_CFSR.read_volatile(),
+            SCB_HFSR.read_volatile(),
+            SCB_MMFAR.read_volatile(),
+            SCB_BFAR.read_volatile(),
+        ]
+    }
+
+    #[cfg(not(armv7m))]
+    [0; 4]
+}
+
+pub(crate) fn encode(frame: &[u32; 8], status: [u32; 4]) -> [u8; SIZE] {
+    let mut bytes = [0; SIZE];
+    bytes[..4].copy_from_slice(&[b'H', b'F', VERSION, WORDS as u8]);
+
+    for (chunk, word) in bytes[4..]
+        .chunks_exact_mut(4)
+        .zip(frame.iter().chain(&status))
+    {
+        chunk.copy_from_slice(&word.to_le_bytes());
+    }
+
+    bytes
+}
+
+/// A decoded crash dump
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CrashDump {
+    /// Register r0
+    pub r0: u32,
+    /// Register r1
+    pub r1: u32,
+    /// Register r2
+    pub r2: u32,
+    /// Register r3
+    pub r3: u32,
+    /// Register r12
+    pub r12: u32,
+    /// Link register
+    pub lr: u32,
+    /// Program counter
+    pub pc: u32,
+    /// Program status register
+    pub xpsr: u32,
+    /// Configurable Fault Status Register
+    pub cfsr: u32,
+    /// HardFault Status Register
+    pub hfsr: u32,
+    /// MemManage Fault Address Register
+    pub mmfar: u32,
+    /// BusFault Address Register
+    pub bfar: u32,
+}
+
+impl CrashDump {
+    /// Decodes a dump written by `dump`
+    ///
+    /// `bytes` must start with the dump; trailing bytes are ignored. This returns `None` if the
+    /// bytes are not a dump or if the dump uses a different format version.
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < SIZE || bytes[..4] != [b'H', b'F', VERSION, WORDS as u8] {
+            return None;
+        }
+
+        let mut words = [0; WORDS];
+        for (word, chunk) in words.iter_mut().zip(bytes[4..SIZE].chunks_exact(4)) {
+            *word = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        }
+
+        let [r0, r1, r2, r3, r12, lr, pc, xpsr, cfsr, hfsr, mmfar, bfar] = words;
+        Some(CrashDump {
+            r0,
+            r1,
+            r2,
+            r3,
+            r12,
+            lr,
+            pc,
+            xpsr,
+            cfsr,
+            hfsr,
+            mmfar,
+            bfar,
+        })
+    }
+}
+
+const CFSR_BITS: &[(u32, &str)] = &[
+    (0, "IACCVIOL"),
+    (1, "DACCVIOL"),
+    (3, "MUNSTKERR"),
+    (4, "MSTKERR"),
+    (5, "MLSPERR"),
+    (7, "MMARVALID"),
+    (8, "IBUSERR"),
+    (9, "PRECISERR"),
+    (10, "IMPRECISERR"),
+    (11, "UNSTKERR"),
+    (12, "STKERR"),
+    (13, "LSPERR"),
+    (15, "BFARVALID"),
+    (16, "UNDEFINSTR"),
+    (17, "INVSTATE"),
+    (18, "INVPC"),
+    (19, "NOCP"),
+    (20, "STKOF"),
+    (24, "UNALIGNED"),
+    (25, "DIVBYZERO"),
+];
+
+const HFSR_BITS: &[(u32, &str)] = &[(1, "VECTTBL"), (30, "FORCED"), (31, "DEBUGEVT")];
+
+struct Flags(u32, &'static [(u32, &'static str)]);
+
+impl fmt::Display for Flags {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut first = true;
+        for (bit, name) in self.1 {
+            if self.0 & (1 << bit) != 0 {
+                f.write_str(if first { " (" } else { ", " })?;
+                f.write_str(name)?;
+                first = false;
+            }
+        }
+
+        if !first {
+            f.write_str(")")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for CrashDump {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "HardFault")?;
+        writeln!(f, "  r0   = {:#010x}", self.r0)?;
+        writeln!(f, "  r1   = {:#010x}", self.r1)?;
+        writeln!(f, "  r2   = {:#010x}", self.r2)?;
+        writeln!(f, "  r3   = {:#010x}", self.r3)?;
+        writeln!(f, "  r12  = {:#010x}", self.r12)?;
+        writeln!(f, "  lr   = {:#010x}", self.lr)?;
+        writeln!(f, "  pc   = {:#010x}", self.pc)?;
+        writeln!(f, "  xpsr = {:#010x}", self.xpsr)?;
+        writeln!(
+            f,
+            "  HFSR = {:#010x}{}",
+            self.hfsr,
+            Flags(self.hfsr, HFSR_BITS)
+        )?;
+        writeln!(
+            f,
+            "  CFSR = {:#010x}{}",
+            self.cfsr,
+            Flags(self.cfsr, CFSR_BITS)
+        )?;
+
+        // MMARVALID
+        if self.cfsr & (1 << 7) != 0 {
+            writeln!(f, "  MMFAR = {:#010x}", self.mmfar)?;
+        }
+
+        // BFARVALID
+        if self.cfsr & (1 << 15) != 0 {
+            writeln!(f, "  BFAR = {:#010x}", self.bfar)?;
+        }
+
+        Ok(())
+    }
+}
@@ -4,6 +4,12 @@
 //! (e.g. CAS loop) to get a handle; and block-free as in the logger never waits for an I/O transfer
 //! (e.g. ITM, UART, etc.) to complete)
 //!
+//! The `shared` option of the `funnel!` macro trades some of these guarantees for RAM: the writers
+//! of the shared ring buffer retry a LDREX/STREX loop, and the outermost one also loops to publish
+//! the data of the writers that preempted it, so writing into it is lock-free but *not* wait-free.
+//! On ARMv6-M these writers mask interrupts for a few instructions instead. Writes into the other
+//! ring buffers are not affected.
+//!
 //! Status: ☢️ **Experimental** ☢️ (ALPHA PRE-RELEASE)
 //!
 //! **SUPER IMPORTANT** Using this crate in a threaded environment will result in an unsound
@@ -34,7 +40,7 @@
 //! use aligned::Aligned;
 //! use cortex_m::itm;
 //!
-//! use funnel::{Drain, funnel, info, trace};
+//! use funnel::{Drains, funnel, info, trace};
 //!
 //! // `NVIC_PRIO_BITS` is the number of priority bits supported by the device
 //! //
@@ -51,6 +57,7 @@
 //!
 //!      // not listing a priority here disables logging at that priority level
 //!      // entering the wrong NVIC_PRIO_BITS value will disable most loggers
+//!      // `funnel::self_check()` detects both mistakes at runtime
 //! });
 //!
 //! #[entry]
@@ -58,11 +65,15 @@
 //!     // ..
 //!     let mut itm: ITM = /* .. */;
 //!
-//!     let drains = Drain::get_all();
+//!     // `Drains` is a singleton; this returns `None` if called a second time
+//!     let drains = Drains::take().unwrap();
 //!
 //!     let mut buf = Aligned([0; 32]); // 4-byte aligned buffer
 //!     loop {
-//!         for (i, drain) in drains.iter().enumerate() {
+//!         for drain in drains.iter() {
+//!             // one stimulus port per priority level
+//!             let port = usize::from(drain.priority().unwrap_or(0));
+//!
 //!             'l: loop {
 //!                 let n = drain.read(&mut buf).len();
 //!
@@ -75,7 +86,7 @@
 //!                 let buf: &Aligned<_, [_]> = &buf;
 //!
 //!                 // will send data in 32-bit chunks
-//!                 itm::write_aligned(&mut itm.stim[i], &buf[..n]);
+//!                 itm::write_aligned(&mut itm.stim[port], &buf[..n]);
 //!             }
 //!         }
 //!     }
@@ -104,6 +115,13 @@
 //! }
 //! ```
 //!
+//! ## Owning the drains
+//!
+//! Two contexts that read from the same ring buffer at the same time corrupt it so `Drains::take`
+//! hands out the drains only once, like `cortex_m::Peripherals::take`. `Drain` is not `Clone`.
+//! In debug builds, `Drain::read` panics when it's not called from thread mode, from the
+//! interrupt selected using the `notify` option (see below) or from the `HardFault` handler.
+//!
 //! ## `Logger`
 //!
 //! The overhead of each macro call can be reduced using one of the `uwrite!` macros on a
@@ -123,6 +141,431 @@
 //! }
 //! ```
 //!
+//! `Logger` also implements `core::fmt::Write` so the `write!` macros of `core` can be used as
+//! well. Note that `core::fmt` generates more code, and is slower, than `ufmt`.
+//!
+//! ## `core::fmt`
+//!
+//! The logging macros use `ufmt` so they only accept types that implement `uDisplay` or `uDebug`.
+//! Each of them has a `_fmt` variant (e.g. `info_fmt!`) that uses `core::fmt` instead and can log
+//! any type that implements `Display` or `Debug`, like most third-party types.
+//!
+//! ``` ignore
+//! use funnel::{info, info_fmt};
+//!
+//! #[interrupt]
+//! fn GPIOA() {
+//!     info!("GPIOA").ok();
+//!
+//!     // `Duration` doesn't implement `uDebug`
+//!     info_fmt!("elapsed: {:?}", Duration::from_millis(1)).ok();
+//! }
+//! ```
+//!
+//! ## Caching the loggers
+//!
+//! `Logger::get` reads the priority of the running interrupt from the NVIC on every call. When the
+//! priorities don't change at runtime the `cache` option of the `funnel!` macro can be used to
+//! resolve the logger of each interrupt only once, on its first log. The value of the option is
+//! the number of interrupts the device has. `Logger::invalidate_cache` must be called after
+//! changing the priority of an interrupt that logs.
+//!
+//! ``` ignore
+//! funnel!(NVIC_PRIO_BITS = 3, cache = 64, {
+//!     1: 32,
+//!     2: 64,
+//! });
+//! ```
+//!
+//! ## BASEPRI
+//!
+//! RTFM resource locks raise the running priority by writing to the BASEPRI register. By default,
+//! `Logger::get` only looks at the priority of the running interrupt so code within a lock logs
+//! into the ring buffer of the interrupt's static priority. With the `basepri` Cargo feature
+//! enabled, which requires an ARMv7-M or ARMv8-M Mainline target, `Logger::get` uses the effective
+//! running priority instead: the highest of the interrupt priority and the BASEPRI priority. This
+//! also lets the thread handler log while BASEPRI is raised.
+//!
+//! This is sound because the context running at effective priority `P` can only be preempted by
+//! interrupts whose priority is *higher* than `P`, and the effective priority of those interrupts
+//! is, at least, their own priority. Thus two contexts that log into the ring buffer of priority
+//! `P` can never preempt each other. Note that the `cache` option is bypassed while BASEPRI is
+//! raised above the priority of the running interrupt.
+//!
+//! If the BASEPRI priority has no ring buffer an interrupt handler keeps logging into the ring
+//! buffer of its own priority, as it does with the feature disabled. That's still sound: raising
+//! BASEPRI only masks more interrupts so no other writer of that ring buffer can preempt it.
+//!
+//! This only holds while BASEPRI stays raised, so a `Logger` obtained within a lock must not be
+//! used after the lock is released: writes through it are dropped (they return an error) unless
+//! BASEPRI is still, at least, as high as when the `Logger` was obtained.
+//!
+//! ## Priority grouping
+//!
+//! The PRIGROUP field of the AIRCR register splits each priority into a group priority, which
+//! decides whether an interrupt can preempt another one, and a sub-priority, which only orders
+//! pending interrupts. Interrupts that have the same group priority never preempt each other so
+//! they share a ring buffer, regardless of their sub-priority. When the application changes
+//! PRIGROUP, pass the same value to the `prigroup` option of the `funnel!` macro: the logical
+//! priorities then range from `1` to `2^min(NVIC_PRIO_BITS, 7 - PRIGROUP)` and the sub-priority
+//! bits are ignored. A PRIGROUP of `7`, which leaves no group priority bits, is not supported.
+//!
+//! ``` ignore
+//! // 4 priority bits: 2 group priority bits (PRIGROUP = 5) and 2 sub-priority bits
+//! funnel!(NVIC_PRIO_BITS = 4, prigroup = 5, {
+//!     1: 32,
+//!     // interrupts whose NVIC priority is `0x80`, `0x90`, `0xA0` or `0xB0`
+//!     2: 64,
+//! });
+//! ```
+//!
+//! ## Wake-on-log
+//!
+//! By default nothing tells the thread handler that new data arrived so it has to poll the
+//! drains. The `notify` option of the `funnel!` macro makes each `Logger` pend an interrupt after
+//! every successful write. The drains can then be processed in that interrupt handler while `main`
+//! sleeps with `WFI`. The interrupt should be given the lowest priority of the system.
+//!
+//! ``` ignore
+//! // `notify` accepts a device interrupt (anything that can be casted to the interrupt number
+//! // using `as u16`) or `PendSV`
+//! funnel!(NVIC_PRIO_BITS = 3, notify = Interrupt::SWI0, {
+//!     1: 32,
+//!     2: 64,
+//! });
+//!
+//! static DRAINS: Mutex<RefCell<Option<Drains>>> = Mutex::new(RefCell::new(None));
+//!
+//! #[entry]
+//! fn main() -> ! {
+//!     interrupt::free(|cs| *DRAINS.borrow(cs).borrow_mut() = Drains::take());
+//!
+//!     // .. unmask `SWI0` and give it the lowest priority ..
+//!
+//!     loop {
+//!         asm::wfi();
+//!     }
+//! }
+//!
+//! #[interrupt]
+//! fn SWI0() {
+//!     let drains = interrupt::free(|cs| DRAINS.borrow(cs).borrow_mut().take());
+//!     for drain in drains.iter().flat_map(|drains| drains.iter()) {
+//!         // ..
+//!     }
+//!     interrupt::free(|cs| *DRAINS.borrow(cs).borrow_mut() = drains);
+//! }
+//! ```
+//!
+//! ## Sizing the ring buffers
+//!
+//! Each `Drain` keeps track of the maximum number of bytes its ring buffer has held; see
+//! `Drain::high_water_mark`. Additionally, the `watermark` and `on_watermark` options of the
+//! `funnel!` macro install a callback that's invoked every time the fill level of a ring buffer
+//! crosses a threshold, specified as a percentage of the buffer size.
+//!
+//! ``` ignore
+//! funnel!(NVIC_PRIO_BITS = 3, watermark = 75, on_watermark = on_watermark, {
+//!     1: 32,
+//!     2: 64,
+//! });
+//!
+//! // called from the interrupt handler whose write made the ring buffer go past the 75% mark
+//! fn on_watermark(level: usize, capacity: usize) {
+//!     // ..
+//! }
+//! ```
+//!
+//! Power of 2 sizes are recommended: the loggers and drains of a power of 2 sized ring buffer use a
+//! mask, instead of a division, to index it. The `power_of_two = true` option turns a size that's
+//! not a power of 2 into a compilation error.
+//!
+//! ## Memory usage
+//!
+//! `Config::get()` describes the ring buffers declared using the `funnel!` macro: their priorities
+//! and sizes, and how much static memory they use in total. The `max_ram` option makes compilation
+//! fail if the ring buffers use more than the given number of bytes.
+//!
+//! ``` ignore
+//! funnel!(NVIC_PRIO_BITS = 3, max_ram = 4096, {
+//!     1: 1024,
+//!     2: 2048,
+//! });
+//!
+//! fn report() {
+//!     let config = Config::get();
+//!
+//!     for buffer in config.buffers {
+//!         // buffer.priority, buffer.nvic_priority, buffer.size, buffer.bytes
+//!     }
+//!
+//!     // config.total_bytes
+//! }
+//! ```
+//!
+//! ## Self-check
+//!
+//! `self_check` compares the `funnel!` declaration against the hardware: it measures the number of
+//! priority bits the NVIC implements, reads the priority grouping (PRIGROUP) and lists the enabled
+//! interrupts whose priority level has no logger (and no shared ring buffer to fall back to). Call
+//! it after configuring and unmasking the interrupts.
+//!
+//! ``` ignore
+//! #[entry]
+//! fn main() -> ! {
+//!     // .. set the priorities and unmask the interrupts ..
+//!
+//!     let check = funnel::self_check();
+//!     if check.nvic_prio_bits != Config::get().nvic_prio_bits {
+//!         // wrong `NVIC_PRIO_BITS` value
+//!     }
+//!
+//!     if check.prigroup != Config::get().prigroup {
+//!         // PRIGROUP doesn't match the `prigroup` option
+//!     }
+//!
+//!     for nr in check.uncovered() {
+//!         // interrupt `nr` won't be able to log
+//!     }
+//!
+//!     // ..
+//! }
+//! ```
+//!
+//! ## Channels
+//!
+//! A priority level can have more than one ring buffer, each one a named channel, to e.g. keep
+//! machine telemetry apart from human-readable logs. `$priority: $size` is a shorthand for
+//! `$priority: { log: $size }`; the logging macros and `Logger::get` use the `log` channel. The
+//! `channel!` macro declares a marker type for any other channel, which is then passed to
+//! `Logger::get_channel` and `Drains::channel`. Attributes can be applied to the whole entry or to
+//! a single channel.
+//!
+//! ``` ignore
+//! funnel!(NVIC_PRIO_BITS = 3, {
+//!     1: 32,
+//!     2: {
+//!         log: 64,
+//!         #[persistent]
+//!         telemetry: 256,
+//!     },
+//! });
+//!
+//! funnel::channel!(pub struct Telemetry = telemetry);
+//!
+//! // logical_priority = 2
+//! #[interrupt]
+//! fn ADC() {
+//!     info!("ADC").ok();
+//!
+//!     if let Some(mut logger) = Logger::get_channel::<Telemetry>() {
+//!         // ..
+//!     }
+//! }
+//!
+//! #[entry]
+//! fn main() -> ! {
+//!     let drains = Drains::take().unwrap();
+//!
+//!     loop {
+//!         for drain in drains.channel::<Telemetry>() {
+//!             // .. send to the telemetry sink ..
+//!         }
+//!
+//!         for drain in drains.iter().filter(|drain| drain.channel() == Some("log")) {
+//!             // .. send to the text sink ..
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! ## Shared ring buffer
+//!
+//! `Logger::get` returns `None` in a context whose priority level has no ring buffer. The `shared`
+//! option of the `funnel!` macro declares a ring buffer, of the given size, that thread mode and
+//! all those interrupt handlers, as well as system exceptions like `SysTick`, write into instead.
+//! Unlike the other ring buffers, several priority levels write into this one so writers reserve
+//! space before copying their data: with a LDREX/STREX loop on ARMv7-M, and with interrupts masked
+//! for a few instructions on ARMv6-M. As NMI and HardFault can't be masked they can't write into
+//! the shared ring buffer on ARMv6-M. Thus, unlike the writes into the other ring buffers, the
+//! writes into the shared ring buffer are not wait-free: a writer retries its reservation when it's
+//! preempted by another writer, and it spins until the data of the writers that preempted it is
+//! published.
+//!
+//! ``` ignore
+//! funnel!(NVIC_PRIO_BITS = 3, shared = 256, {
+//!     // priority 1 is busy; it gets its own ring buffer
+//!     1: 128,
+//!     // priorities 2 to 8, and thread mode, write into the shared ring buffer
+//! });
+//!
+//! #[entry]
+//! fn main() -> ! {
+//!     let drains = Drains::take().unwrap();
+//!
+//!     loop {
+//!         for drain in drains.iter().chain(drains.shared()) {
+//!             // ..
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! Each write into the shared ring buffer is atomic but a message written using several writes,
+//! like the ones formatted by the logging macros, may be interleaved with the messages of higher
+//! priority interrupts. Named channels and the `defmt` logger don't use the shared ring buffer.
+//!
+//! ## Linker sections
+//!
+//! The `link_section` option places all the ring buffers in the given linker section (e.g. a
+//! faster `.ccmram` region); the `#[link_section]` attribute does the same for a single ring buffer
+//! and takes precedence over the option.
+//!
+//! ``` ignore
+//! funnel!(NVIC_PRIO_BITS = 3, link_section = ".ccmram", {
+//!     1: 32,
+//!
+//!     // `cortex-m-rt` doesn't initialize this section at boot
+//!     #[link_section = ".uninit.funnel"]
+//!     2: 64,
+//! });
+//! ```
+//!
+//! ## Persistent ring buffers
+//!
+//! Ring buffers placed in a section that's not initialized at boot, like `cortex-m-rt`'s
+//! `.uninit`, keep their contents across resets (e.g. a watchdog reset). The `#[persistent]`
+//! attribute places a ring buffer in the `.uninit` section.
+//!
+//! The header of each ring buffer is validated by `Drains::take`, which resets the ring buffers
+//! that hold garbage (e.g. after a power cycle), so `Drains::take` must be called before any
+//! interrupt handler logs. The data that was written before the reset, but not
+//! drained, can then be retrieved using `Drain::recover_previous_boot`.
+//!
+//! ``` ignore
+//! funnel!(NVIC_PRIO_BITS = 3, {
+//!     #[persistent]
+//!     1: 32,
+//!     2: 64,
+//! });
+//!
+//! #[entry]
+//! fn main() -> ! {
+//!     let drains = Drains::take().unwrap();
+//!
+//!     let mut buf = [0; 32];
+//!     for drain in drains.iter() {
+//!         loop {
+//!             let bytes = drain.recover_previous_boot(&mut buf);
+//!
+//!             if bytes.is_empty() {
+//!                 break;
+//!             }
+//!
+//!             // .. send these bytes to the host ..
+//!         }
+//!     }
+//!
+//!     // .. unmask interrupts and drain as usual ..
+//! }
+//! ```
+//!
+//! ## Async draining
+//!
+//! With the `async` Cargo feature enabled the thread handler can `await` new data instead of
+//! polling the drains in a busy loop. `Drain::readable()` returns a future that resolves once
+//! any of the ring buffers has data; `Logger`s wake the task that's awaiting it after each write.
+//!
+//! ``` ignore
+//! use funnel::{Drain, Drains};
+//!
+//! async fn drain_all(mut itm: ITM, drains: Drains) -> ! {
+//!     let mut buf = [0; 32];
+//!     loop {
+//!         // the executor can sleep (e.g. `WFI`) until an interrupt handler logs something
+//!         Drain::readable().await;
+//!
+//!         for (i, drain) in drains.iter().enumerate() {
+//!             'l: loop {
+//!                 let bytes = drain.read(&mut buf);
+//!
+//!                 if bytes.is_empty() {
+//!                     break 'l;
+//!                 }
+//!
+//!                 itm::write_all(&mut itm.stim[i], bytes);
+//!             }
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! ## HardFault crash dumps
+//!
+//! A `HardFault` entry in the `funnel!` macro declares a ring buffer for the `HardFault` handler.
+//! `hardfault::dump` writes the stacked exception frame plus the fault status registers into this
+//! ring buffer. Make the ring buffer `#[persistent]` to retrieve the dump after the device resets.
+//! The `hardfault::CrashDump` type decodes the dump on the host.
+//!
+//! ``` ignore
+//! funnel!(NVIC_PRIO_BITS = 3, {
+//!     #[persistent]
+//!     HardFault: 64,
+//!     1: 32,
+//!     2: 64,
+//! });
+//!
+//! #[exception]
+//! fn HardFault(ef: &ExceptionFrame) -> ! {
+//!     // NOTE `ExceptionFrame` is `#[repr(C)]` and contains eight `u32` fields
+//!     funnel::hardfault::dump(unsafe { &*(ef as *const ExceptionFrame as *const [u32; 8]) }).ok();
+//!
+//!     SCB::sys_reset()
+//! }
+//!
+//! #[entry]
+//! fn main() -> ! {
+//!     let drains = Drains::take().unwrap();
+//!
+//!     if let Some(drain) = drains.hardfault() {
+//!         // .. send the contents of `drain` to the host ..
+//!     }
+//!
+//!     // ..
+//! }
+//! ```
+//!
+//! ## Panic handler
+//!
+//! With the `panic` Cargo feature enabled this crate provides a `#[panic_handler]` that flushes
+//! the ring buffers through a blocking sink before halting or resetting the device. Otherwise, the
+//! data that was not drained before the panic is lost. See the `panic` module for details.
+//!
+//! ## `defmt` global logger
+//!
+//! With the `defmt` Cargo feature enabled `funnel` is the global logger of the `defmt` crate; each
+//! `defmt` frame is encoded into the ring buffer of the caller's priority level. See the `defmt`
+//! module for details.
+//!
+//! ## `log` backend
+//!
+//! With the `log` Cargo feature enabled `funnel` can be registered as the global logger of the
+//! `log` crate so the logs of third-party crates end up in the ring buffers. See the `log` module
+//! for details.
+//!
+//! ## Exception tags
+//!
+//! Several interrupt handlers may share the ring buffer of a priority level. With the
+//! `exception_tag` Cargo feature enabled each record is tagged with the number of the exception
+//! that logged it (VECTACTIVE; `0` = thread mode, `16 + n` = device interrupt `n`) so the host can
+//! map records back to interrupt names, e.g. using the device's SVD file. The logging macros and
+//! the `log` backend prefix each line with the number: `info!("GPIOF")` writes `[21] GPIOF` and
+//! `log::info!("GPIOF")` writes `[21] INFO GPIOF`, as the `log` backend also writes the level. Each
+//! `defmt` frame carries it as one extra byte (see the `defmt` module). The tag goes into the ring
+//! buffer in the same write as the start of the line so a record that doesn't fit never leaves a
+//! bare tag behind. Writes through a `Logger` obtained using `Logger::get` or `Logger::get_channel`
+//! are not tagged.
+//!
 //! # Logging levels
 //!
 //! `funnel` supports 5 logging level: Trace, Debug, Info, Warn and Error, sorted in increasing
@@ -170,6 +613,11 @@
 //! Where `S` is a 45-byte long string, `N = usize::max_value()`, the `drain` function is
 //! `ptr::read_volatile`-ing each byte and the ITM was clocked at 2 MHz.
 //!
+//! These numbers were measured before power of 2 sized ring buffers were indexed using a mask,
+//! which replaces the modulo operation (a `udiv` instruction, or a call to `__aeabi_uidivmod` on
+//! ARMv6-M) of each write and each read. The `bench` example measures them for a power of 2 sized
+//! ring buffer and for a ring buffer of a size that's not a power of 2.
+//!
 //! # Potential improvements / alternatives
 //!
 //! Instead of draining the ring buffers at the lowest priority one could drain the buffers using
@@ -184,16 +632,62 @@
 
 use core::{
     cell::UnsafeCell,
-    cmp, ptr,
-    sync::atomic::{self, AtomicUsize, Ordering},
+    cmp, fmt,
+    marker::PhantomData,
+    mem,
+    ops::Deref,
+    ptr,
+    sync::atomic::{self, AtomicBool, AtomicU32, AtomicU8, AtomicUsize, Ordering},
+};
+
+#[cfg(feature = "async")]
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll, Waker},
 };
 
 use ufmt::uWrite;
 
+#[cfg(any(test, fuzzing))]
+extern crate std;
+
+// Point at which the interleaving tests may run a `Logger`, as an interrupt handler would; this
+// expands to nothing outside of tests
+macro_rules! preemption_point {
+    () => {
+        #[cfg(test)]
+        crate::tests::preempt();
+    };
+}
+
+#[cfg(feature = "defmt")]
+pub mod defmt;
+pub mod hardfault;
+#[cfg(feature = "log")]
+pub mod log;
+#[cfg(any(test, fuzzing))]
+#[doc(hidden)]
+pub mod model;
+#[cfg(feature = "panic")]
+pub mod panic;
+
 /// Declares loggers for each priority level
 pub use cortex_m_funnel_macros::funnel;
 #[doc(hidden)]
 pub use ufmt::uwriteln;
+// Used by `_flog_fmt`
+#[doc(hidden)]
+pub use core::fmt::Write as _FmtWrite;
+
+// Cortex-M MMIO registers
+#[cfg(armv7m)]
+const ICTR: *const u32 = 0xE000_E004 as *const u32;
+const NVIC_ISER: *const u32 = 0xE000_E100 as *const u32;
+const NVIC_ISPR: *mut u32 = 0xE000_E200 as *mut u32;
+const NVIC_IPR: *mut u32 = 0xE000_E400 as *mut u32;
+const SCB_ICSR: *mut u32 = 0xE000_ED04 as *mut u32;
+const SCB_AIRCR: *const u32 = 0xE000_ED0C as *const u32;
 
 /// IMPLEMENTATION DETAIL
 // `static [mut]` variables cannot contain references to `static mut` variables so we lie about the
@@ -205,11 +699,60 @@ pub struct Inner<B>
 where
     B: ?Sized,
 {
-    write: UnsafeCell<usize>,
-    read: UnsafeCell<usize>,
+    // NOTE only the `Logger` stores to `write` and `hwm`, and only the `Drain` stores to `read` and
+    // `previous`. All the accesses are `Relaxed` loads and stores, which compile to plain `ldr` and
+    // `str` instructions; `compiler_fence`s order them with respect to the buffer accesses, which
+    // is enough on a single core system
+    write: AtomicUsize,
+    read: AtomicUsize,
+    // the maximum number of bytes the ring buffer has held at any point
+    hwm: AtomicUsize,
+    // NOTE the ring buffer may be placed in a linker section that's not initialized at boot so all
+    // the fields of the header must be mutable and checked for validity (see `validate`)
+    //
+    // NOTE `magic` and `crc` are stored XOR-ed with their values in a fresh header so that a fresh
+    // header is all zeros; this keeps ring buffers in `.bss` (and in `NOLOAD` sections)
+    // `magic ^ MAGIC`, i.e. `0` in a valid header
+    magic: AtomicU32,
+    // number of boots this ring buffer has survived; `0` means initialized by the runtime
+    generation: AtomicU32,
+    // `crc32([MAGIC, generation]) ^ crc32([MAGIC, 0])`
+    crc: AtomicU32,
+    // number of bytes, at the front of the ring buffer, that were written before the last reset
+    previous: AtomicUsize,
+    // NOTE the `Logger` writes into the free part of the buffer and the `Drain` reads from the used
+    // part of the buffer so they never access the same bytes at the same time. To avoid asserting
+    // unique access to the whole buffer neither creates references to it; they use raw pointers
     buffer: UnsafeCell<B>,
 }
 
+// "FUNL" in little endian
+const MAGIC: u32 = 0x4c4e_5546;
+
+// `crc` field of a header that has survived `generation` boots
+const fn crc(generation: u32) -> u32 {
+    crc32([MAGIC, generation]) ^ crc32([MAGIC, 0])
+}
+
+// CRC-32 (IEEE 802.3) of the given words, in little endian order
+const fn crc32(words: [u32; 2]) -> u32 {
+    let mut crc = !0u32;
+    let mut i = 0;
+    while i < words.len() * 32 {
+        if i % 8 == 0 {
+            crc ^= (words[i / 32] >> (i % 32)) & 0xff;
+        }
+
+        crc = if crc & 1 == 1 {
+            (crc >> 1) ^ 0xedb8_8320
+        } else {
+            crc >> 1
+        };
+        i += 1;
+    }
+    !crc
+}
+
 unsafe impl<B> Sync for Inner<B> where B: ?Sized {}
 
 impl<B> Inner<B> {
@@ -217,127 +760,842 @@ impl<B> Inner<B> {
     #[doc(hidden)]
     pub const fn new(buffer: B) -> Self {
         Self {
-            write: UnsafeCell::new(0),
-            read: UnsafeCell::new(0),
+            write: AtomicUsize::new(0),
+            read: AtomicUsize::new(0),
+            hwm: AtomicUsize::new(0),
+            magic: AtomicU32::new(0),
+            generation: AtomicU32::new(0),
+            crc: AtomicU32::new(0),
+            previous: AtomicUsize::new(0),
             buffer: UnsafeCell::new(buffer),
         }
     }
 }
 
-/// A logger tied a particular priority level
-// NOTE: NOT `Sync` or `Send`
-#[repr(transparent)]
-pub struct Logger {
-    inner: &'static Inner<[u8]>,
+impl Inner<[u8]> {
+    // Length of the buffer
+    fn len(&self) -> usize {
+        mem::size_of_val(&self.buffer)
+    }
+
+    // Pointer to the start of the buffer
+    fn as_ptr(&self) -> *mut u8 {
+        self.buffer.get() as *mut u8
+    }
 }
 
-impl Logger {
-    /// Gets the `funnel` logger associated to the caller's priority level
-    ///
-    /// This returns `None` if no logger was associated to the priority level
-    pub fn get() -> Option<Self> {
-        if cfg!(not(cortex_m)) {
-            return None;
-        }
+/// IMPLEMENTATION DETAIL
+// The ring buffer of the `shared` option of the `funnel!` macro, which any context that has no ring
+// buffer of its own writes into. Only the `funnel!` macro uses this type
+#[doc(hidden)]
+#[repr(C)]
+pub struct Shared<B>
+where
+    B: ?Sized,
+{
+    // end of the space reserved by the writers; `inner.write` is the end of the data they have
+    // published. Unlike `write`, several priority levels store to it (see
+    // `Logger::append_reserved`)
+    reserve: AtomicUsize,
+    inner: Inner<B>,
+}
 
-        if (cfg!(debug_assertions) && cfg!(feature = "max_level_off"))
-            || cfg!(feature = "release_max_level_off")
-        {
-            return None;
+impl<B> Shared<B> {
+    // IMPLEMENTATION DETAIL
+    #[doc(hidden)]
+    pub const fn new(buffer: B) -> Self {
+        Self {
+            reserve: AtomicUsize::new(0),
+            inner: Inner::new(buffer),
         }
+    }
+}
 
-        // Cortex-M MMIO registers
-        const SCB_ICSR: *const u32 = 0xE000_ED04 as *const u32;
-        const NVIC_IPR: *const u32 = 0xE000_E400 as *const u32;
+/// IMPLEMENTATION DETAIL; DO NOT USE
+// A ring buffer plus the constants of its pointer arithmetic, which only depend on the length of
+// the buffer. These constants live in Flash, next to the reference to the ring buffer (e.g. in the
+// `__FUNNEL_D` table of the `funnel!` expansion), because the header of the ring buffer must stay
+// all zeros (see `Inner`). Only the `funnel!` macro creates values of this type
+#[doc(hidden)]
+#[derive(Clone, Copy)]
+pub struct Ring {
+    inner: &'static Inner<[u8]>,
+    // the `reserve` pointer of the shared ring buffer; `None` for the other ring buffers
+    reserve: Option<&'static AtomicUsize>,
+    // `wrap_point` of the length of the buffer, which is `0` for power of 2 sized ring buffers.
+    // These map pointers to buffer indices using a mask instead of a modulo operation, which
+    // compiles to a `udiv` instruction (or to a call to a software division routine on ARMv6-M,
+    // which has no hardware divide)
+    wrap: usize,
+    // the description of the ring buffer in `Config`; `None` for the HardFault and shared ring
+    // buffers
+    config: Option<&'static Buffer>,
+}
 
-        extern "Rust" {
-            // NOTE The expansion of `funnel!` declares `__funnel_drains` as a function with signature
-            // `fn() -> Option<&'static Inner<[u8]>>` so here we are implicitly transmuting `&'static
-            // Inner<[u8]>` into `Logger` but this should be fine because they are equivalent due to
-            // `#[repr(transparent)]`
-            fn __funnel_logger(nvic_prio: u8) -> Option<Logger>;
+impl Ring {
+    // IMPLEMENTATION DETAIL
+    //
+    // NOTE(unsafe) only the expansion of `funnel!` may call this; it creates a single `Ring` per
+    // ring buffer and ties it to a single priority level, its only writer
+    #[doc(hidden)]
+    pub const unsafe fn new<const N: usize>(
+        inner: &'static Inner<[u8; N]>,
+        config: Option<&'static Buffer>,
+    ) -> Self {
+        Ring {
+            inner,
+            reserve: None,
+            wrap: wrap_point(N),
+            config,
+        }
+    }
+
+    // IMPLEMENTATION DETAIL
+    #[doc(hidden)]
+    pub const fn shared<const N: usize>(shared: &'static Shared<[u8; N]>) -> Self {
+        Ring {
+            inner: &shared.inner,
+            reserve: Some(&shared.reserve),
+            wrap: wrap_point(N),
+            config: None,
+        }
+    }
+
+    // Maps a `write` or `read` pointer to an index into the buffer
+    #[inline(always)]
+    fn index(&self, pointer: usize) -> usize {
+        let blen = self.len();
+        if self.wrap == 0 {
+            pointer & (blen - 1)
+        } else {
+            pointer % blen
+        }
+    }
+
+    // Returns `pointer + n`, where `n <= blen`
+    //
+    // NOTE this and `distance` use wrapping arithmetic because the pointers may be garbage, and thus
+    // past the wrap point, before `validate` runs
+    #[inline(always)]
+    fn advance(&self, pointer: usize, n: usize) -> usize {
+        if self.wrap == 0 {
+            pointer.wrapping_add(n)
+        } else {
+            let left = self.wrap.wrapping_sub(pointer);
+            if n >= left {
+                n - left
+            } else {
+                pointer.wrapping_add(n)
+            }
+        }
+    }
+
+    // Returns `to - from`, the number of bytes between the `from` and `to` pointers
+    #[inline(always)]
+    fn distance(&self, from: usize, to: usize) -> usize {
+        if self.wrap == 0 {
+            to.wrapping_sub(from)
+        } else if to >= from {
+            to - from
+        } else {
+            to.wrapping_add(self.wrap.wrapping_sub(from))
+        }
+    }
+
+    // Returns `true` if `pointer` is within the range used by the `write` and `read` pointers
+    fn is_valid(&self, pointer: usize) -> bool {
+        self.wrap == 0 || pointer < self.wrap
+    }
+
+    // Copies `input` into the buffer, starting at the `write` pointer
+    //
+    // NOTE the caller must own the `input.len()` bytes that follow `write`, which must fit in the
+    // buffer
+    unsafe fn copy_from(&self, write: usize, input: &[u8]) {
+        let blen = self.len();
+        let ilen = input.len();
+        let p = self.as_ptr();
+        let w = self.index(write);
+
+        // NOTE we use `ptr::copy_nonoverlapping` instead of `copy_from_slice` to avoid
+        // panicking branches
+        if w + ilen > blen {
+            // two memcpy-s
+            let mid = blen - w;
+            // buffer[w..].copy_from_slice(&input[..mid]);
+            ptr::copy_nonoverlapping(input.as_ptr(), p.add(w), mid);
+            // buffer[..ilen - mid].copy_from_slice(&input[mid..]);
+            ptr::copy_nonoverlapping(input.as_ptr().add(mid), p, ilen - mid);
+        } else {
+            // single memcpy
+            // buffer[w..w + ilen].copy_from_slice(&input);
+            ptr::copy_nonoverlapping(input.as_ptr(), p.add(w), ilen);
+        }
+    }
+
+    // Resets the ring buffer if its header contains garbage, which is the case when the ring
+    // buffer is placed in a linker section that's not initialized at boot (e.g. `.uninit`).
+    // Otherwise, records how many bytes were left in the ring buffer by the previous boot. This
+    // must be called once per boot
+    //
+    // NOTE a `Logger` that runs before this check may write into the ring buffer. That's memory
+    // safe, even if the pointers are garbage: `index` always maps the `write` pointer to an index
+    // into the buffer, `advance` and `distance` use wrapping arithmetic, and `Logger::append` checks
+    // that `used` doesn't exceed the free space without computing `ilen + used`, which could
+    // overflow. At worst the write lands at the wrong place, or fails, and then this check resets
+    // the ring buffer. `Drain::read`, on the other hand, relies on the invariant
+    // `write - read <= buffer.len()`, so it only runs after this check
+    fn validate(&self) {
+        let blen = self.len();
+        let write = self.write.load(Ordering::Relaxed);
+        let read = self.read.load(Ordering::Relaxed);
+        let hwm = self.hwm.load(Ordering::Relaxed);
+        let magic = self.magic.load(Ordering::Relaxed);
+        let generation = self.generation.load(Ordering::Relaxed);
+
+        let generation = if magic != 0
+            || self.crc.load(Ordering::Relaxed) != crc(generation)
+            || !self.is_valid(write)
+            || !self.is_valid(read)
+            || self.distance(read, write) > blen
+            || hwm > blen
+        {
+            self.write.store(0, Ordering::Relaxed);
+            self.read.store(0, Ordering::Relaxed);
+            self.hwm.store(0, Ordering::Relaxed);
+            self.previous.store(0, Ordering::Relaxed);
+            atomic::compiler_fence(Ordering::Release);
+            self.magic.store(0, Ordering::Relaxed);
+
+            1
+        } else if generation == 0 {
+            // this ring buffer was initialized by the runtime (e.g. `.data`) so everything in it
+            // was written during this boot
+            self.previous.store(0, Ordering::Relaxed);
+
+            1
+        } else {
+            self.previous
+                .store(self.distance(read, write), Ordering::Relaxed);
+
+            // `0` is reserved for ring buffers initialized by the runtime
+            generation.checked_add(1).unwrap_or(1)
+        };
+
+        self.generation.store(generation, Ordering::Relaxed);
+        self.crc.store(crc(generation), Ordering::Relaxed);
+    }
+}
+
+impl Deref for Ring {
+    type Target = Inner<[u8]>;
+
+    fn deref(&self) -> &Inner<[u8]> {
+        self.inner
+    }
+}
+
+// The `write` and `read` pointers wrap around at this value, which is a multiple of `blen`, so that
+// consecutive pointers always map to consecutive buffer indices. Power of 2 sized ring buffers
+// instead wrap around at `usize::MAX + 1` (wrapping arithmetic), which this function returns as `0`
+const fn wrap_point(blen: usize) -> usize {
+    if blen.is_power_of_two() {
+        0
+    } else {
+        usize::MAX - usize::MAX % blen
+    }
+}
+
+/// IMPLEMENTATION DETAIL; DO NOT USE
+// Value of the entries of the logger cache that have not been resolved yet
+#[doc(hidden)]
+pub const UNRESOLVED: u8 = 0xFE;
+
+/// IMPLEMENTATION DETAIL; DO NOT USE
+// The state of the `defmt` frame being written into a ring buffer; this is zero sized unless the
+// "defmt" feature is enabled. Only the `funnel!` macro uses this type
+#[doc(hidden)]
+pub struct Frame {
+    #[cfg(feature = "defmt")]
+    #[cfg_attr(not(cortex_m), allow(dead_code))]
+    state: UnsafeCell<defmt::State>,
+}
+
+// NOTE like the `Logger`, a `Frame` is only accessed from the priority level of its ring buffer
+unsafe impl Sync for Frame {}
+
+impl Frame {
+    /// IMPLEMENTATION DETAIL; DO NOT USE
+    #[allow(clippy::new_without_default)]
+    pub const fn new() -> Self {
+        Frame {
+            #[cfg(feature = "defmt")]
+            state: UnsafeCell::new(defmt::State::new()),
+        }
+    }
+}
+
+// Reads the priority of interrupt `nr` from the NVIC
+unsafe fn nvic_priority(nr: u16) -> u8 {
+    // assuming ARMv6-M (the lowest common denominator), IPR is *not* byte addressable so we perform
+    // word-size reads
+    // NOTE `nr` will always be less than `496`
+    let ipr = NVIC_IPR.add((nr >> 2) as usize).read_volatile();
+
+    (ipr >> (8 * (nr % 4))) as u8
+}
+
+// Reads the BASEPRI register
+#[cfg(feature = "basepri")]
+fn basepri() -> u8 {
+    #[cfg(armv7m)]
+    unsafe {
+        let basepri: u32;
+        core::arch::asm!(
+            "mrs {}, BASEPRI",
+            out(reg) basepri,
+            options(nomem, nostack, preserves_flags)
+        );
+        basepri as u8
+    }
+
+    #[cfg(not(armv7m))]
+    0
+}
+
+// Returns the index, into the `len` ring buffers, of the ring buffer of the BASEPRI priority if it's
+// higher than the priority of the running interrupt, `nvic_prio`, and the BASEPRI priority has a
+// ring buffer. `index` maps an NVIC priority to an index
+#[cfg(feature = "basepri")]
+fn ceiling_index(basepri: u8, nvic_prio: u8, len: usize, index: impl Fn(u8) -> u8) -> Option<u8> {
+    // NOTE lower values mean higher priorities and a BASEPRI of `0` doesn't mask anything
+    if basepri != 0 && basepri < nvic_prio {
+        let i = index(basepri);
+        if usize::from(i) < len {
+            return Some(i);
+        }
+    }
+
+    None
+}
+
+// Runs `f` with interrupts masked
+fn interrupt_free<R>(f: impl FnOnce() -> R) -> R {
+    #[cfg(cortex_m)]
+    unsafe {
+        let primask: u32;
+        core::arch::asm!(
+            "mrs {}, PRIMASK",
+            out(reg) primask,
+            options(nomem, nostack, preserves_flags)
+        );
+        core::arch::asm!("cpsid i", options(nostack, preserves_flags));
+
+        let r = f();
+
+        // PM = 0: interrupts were not masked on entry
+        if primask & 1 == 0 {
+            core::arch::asm!("cpsie i", options(nostack, preserves_flags));
+        }
+
+        r
+    }
+
+    #[cfg(not(cortex_m))]
+    f()
+}
+
+// Updates `atomic` to the value returned by `f`, unless it returns `None`; returns the previous
+// value. ARMv7-M uses a LDREX/STREX loop, which runs `f` again if an interrupt handler preempted
+// the update; ARMv6-M has no such instructions so interrupts are masked instead
+fn fetch_update(
+    atomic: &AtomicUsize,
+    f: impl FnMut(usize) -> Option<usize>,
+) -> Result<usize, usize> {
+    #[cfg(target_has_atomic = "ptr")]
+    {
+        atomic.fetch_update(Ordering::Relaxed, Ordering::Relaxed, f)
+    }
+
+    #[cfg(not(target_has_atomic = "ptr"))]
+    interrupt_free(|| {
+        let mut f = f;
+        let old = atomic.load(Ordering::Relaxed);
+        let new = f(old).ok_or(old)?;
+        atomic.store(new, Ordering::Relaxed);
+        Ok(old)
+    })
+}
+
+#[cfg(all(feature = "basepri", cortex_m, not(armv7m)))]
+compile_error!("the `basepri` feature requires an ARMv7-M or ARMv8-M Mainline target");
+
+/// A named ring buffer declared using the `funnel!` macro, other than the default `log` one
+///
+/// Use the `channel!` macro to implement this trait.
+///
+/// # Safety
+///
+/// Implementors must be generated by the `channel!` macro: `index` must return an index into the
+/// ring buffers declared using the `funnel!` macro, or an out of bounds value, and `NAME` must
+/// match the channel those ring buffers belong to.
+pub unsafe trait Channel {
+    /// Name of the channel, as written in the `funnel!` macro
+    const NAME: &'static str;
+
+    /// IMPLEMENTATION DETAIL; DO NOT USE
+    #[doc(hidden)]
+    fn index(nvic_prio: u8) -> u8;
+}
+
+/// Declares a marker type for a channel declared using the `funnel!` macro
+///
+/// The marker type can be declared in a library; linking fails if the `funnel!` macro doesn't
+/// declare the channel at any priority level.
+///
+/// ``` ignore
+/// funnel::channel!(pub struct Telemetry = telemetry);
+///
+/// if let Some(mut logger) = Logger::get_channel::<Telemetry>() {
+///     // ..
+/// }
+/// ```
+#[macro_export]
+macro_rules! channel {
+    ($(#[$attr:meta])* $vis:vis struct $ty:ident = $name:ident) => {
+        $(#[$attr])*
+        $vis struct $ty;
+
+        unsafe impl $crate::Channel for $ty {
+            const NAME: &'static str = stringify!($name);
+
+            fn index(nvic_prio: u8) -> u8 {
+                extern "Rust" {
+                    // NOTE the expansion of `funnel!` defines this function for each channel
+                    #[link_name = concat!("__funnel_channel_", stringify!($name))]
+                    fn index(nvic_prio: u8) -> u8;
+                }
+
+                unsafe { index(nvic_prio) }
+            }
+        }
+    };
+}
+
+/// A logger tied a particular priority level
+// NOTE: NOT `Sync` or `Send`; each ring buffer has a single writer, the priority level the logger
+// was retrieved at
+pub struct Logger {
+    inner: Ring,
+    // the BASEPRI this logger was resolved with; `0` if BASEPRI didn't pick its ring buffer
+    #[cfg(feature = "basepri")]
+    basepri: u8,
+    _not_send_or_sync: PhantomData<*const ()>,
+}
+
+impl Logger {
+    fn new(inner: Ring) -> Self {
+        Logger {
+            inner,
+            #[cfg(feature = "basepri")]
+            basepri: 0,
+            _not_send_or_sync: PhantomData,
+        }
+    }
+
+    /// Gets the `funnel` logger associated to the caller's priority level
+    ///
+    /// If no logger was associated to the priority level this returns the logger of the shared ring
+    /// buffer, if the `shared` option of the `funnel!` macro was used, or `None`.
+    pub fn get() -> Option<Self> {
+        if cfg!(not(cortex_m)) {
+            return None;
+        }
+
+        extern "Rust" {
+            // NOTE the expansion of `funnel!` defines this function; it calls `get_or_shared` if
+            // the `shared` option was used or `get_exclusive` otherwise
+            fn __funnel_get() -> Option<Logger>;
+        }
+
+        unsafe { __funnel_get() }
+    }
+
+    /// IMPLEMENTATION DETAIL; DO NOT USE
+    // `get` when the `shared` option was not used
+    //
+    // NOTE(unsafe) only the expansion of `funnel!` may call this
+    #[doc(hidden)]
+    pub unsafe fn get_exclusive() -> Option<Self> {
+        Self::resolve().map(|(logger, _)| logger)
+    }
+
+    /// IMPLEMENTATION DETAIL; DO NOT USE
+    // `get` when the `shared` option was used
+    //
+    // NOTE(unsafe) only the expansion of `funnel!` may call this; `shared` must be its shared ring
+    // buffer, which is the only one that several priority levels may write into
+    #[doc(hidden)]
+    pub unsafe fn get_or_shared(shared: Ring) -> Option<Self> {
+        Self::get_exclusive().or_else(|| Self::shared(shared))
+    }
+
+    /// Gets the logger of the given `channel` associated to the caller's priority level
+    ///
+    /// This returns `None` if the channel has no ring buffer at the priority level. Unlike `get`,
+    /// this doesn't use the cache enabled by the `cache` option of the `funnel!` macro.
+    pub fn get_channel<C>() -> Option<Self>
+    where
+        C: Channel,
+    {
+        if cfg!(not(cortex_m)) {
+            return None;
+        }
+
+        Self::lookup(Some(C::index)).map(|(logger, _)| logger)
+    }
+
+    // Like `get` but also returns the index of the logger into `__funnel_frames`
+    pub(crate) fn resolve() -> Option<(Self, usize)> {
+        Self::lookup(None)
+    }
+
+    // The logger of the shared ring buffer
+    fn shared(shared: Ring) -> Option<Self> {
+        if cfg!(not(cortex_m)) {
+            return None;
+        }
+
+        if (cfg!(debug_assertions) && cfg!(feature = "max_level_off"))
+            || cfg!(feature = "release_max_level_off")
+        {
+            return None;
+        }
+
+        // NOTE NMI (2) and HardFault (3) can't be masked so they would preempt the critical
+        // sections ARMv6-M uses to reserve space (see `fetch_update`)
+        if cfg!(not(target_has_atomic = "ptr")) && matches!(exception(), 2 | 3) {
+            return None;
+        }
+
+        Some(Logger::new(shared))
+    }
+
+    // `channel` maps an NVIC priority to an index into `__funnel_drains`; `None` means the `log`
+    // channel
+    fn lookup(channel: Option<fn(u8) -> u8>) -> Option<(Self, usize)> {
+        if cfg!(not(cortex_m)) {
+            return None;
+        }
+
+        if (cfg!(debug_assertions) && cfg!(feature = "max_level_off"))
+            || cfg!(feature = "release_max_level_off")
+        {
+            return None;
+        }
+
+        extern "Rust" {
+            // NOTE see `Drain::get_all`
+            fn __funnel_drains() -> &'static [Drain];
+
+            fn __funnel_hardfault() -> Option<Ring>;
+
+            // Maps an NVIC priority to an index into `__funnel_drains`; an out of bounds index
+            // means that no logger was associated to the priority level
+            fn __funnel_index(nvic_prio: u8) -> u8;
+
+            // One entry per interrupt; empty if the `cache` option was not used
+            fn __funnel_cache() -> &'static [AtomicU8];
         }
 
         unsafe {
-            let icsr = SCB_ICSR.read_volatile() as u8;
+            let active = exception();
+
+            let logger = |index: u8| {
+                let index = usize::from(index);
+                __funnel_drains()
+                    .get(index)
+                    .map(|drain| (Logger::new(drain.inner), index))
+            };
 
-            if icsr == 0 {
+            let index = |nvic_prio: u8| match channel {
+                Some(index) => index(nvic_prio),
+                None => __funnel_index(nvic_prio),
+            };
+
+            if active == 0 {
                 // thread mode
+                #[cfg(feature = "basepri")]
+                {
+                    // NOTE a BASEPRI of `0` doesn't mask any interrupt
+                    let basepri = basepri();
+                    if basepri != 0 {
+                        return logger(index(basepri))
+                            .map(|(logger, index)| (logger.masked_by(basepri), index));
+                    }
+                }
+
                 None
-            } else if icsr < 16 {
-                // TODO do something about exceptions -- NMI and HardFault are annoying because they
-                // have exceptional priorities
+            } else if active == 3 {
+                // HardFault can only be preempted by NMI, which has no logger, and a fault within
+                // the HardFault handler locks up the processor so this logger is never re-entered
+                if channel.is_some() {
+                    // the HardFault ring buffer has no channels
+                    return None;
+                }
+
+                __funnel_hardfault().map(|ring| (Logger::new(ring), __funnel_drains().len()))
+            } else if active < 16 {
+                // TODO do something about the other exceptions -- NMI is annoying because it has an
+                // exceptional priority
                 None
             } else {
-                // assuming ARMv6-M (the lowest common denominator), IPR is *not* byte addressable
-                // so we perform word-size reads
-                let nr = icsr - 16;
-
-                // NOTE `nr` will always be less than `256`
-                let ipr = NVIC_IPR.add((nr >> 2) as usize).read_volatile();
+                let nr = active - 16;
+
+                #[cfg(feature = "basepri")]
+                {
+                    let basepri = basepri();
+                    let len = __funnel_drains().len();
+                    if let Some(i) = ceiling_index(basepri, nvic_priority(nr), len, index) {
+                        return logger(i).map(|(logger, index)| (logger.masked_by(basepri), index));
+                    }
+                    // otherwise use the ring buffer of the interrupt's own priority, as if
+                    // BASEPRI was not raised
+                }
 
-                let nvic_prio = (ipr >> (8 * (nr % 4))) as u8;
+                let entry = if channel.is_none() {
+                    __funnel_cache().get(usize::from(nr))
+                } else {
+                    None
+                };
+
+                let index = if let Some(entry) = entry {
+                    // NOTE only this interrupt handler resolves this entry and it can't be
+                    // preempted by itself so this load-store sequence is not a data race
+                    let mut i = entry.load(Ordering::Relaxed);
+                    if i == UNRESOLVED {
+                        i = index(nvic_priority(nr));
+                        entry.store(i, Ordering::Relaxed);
+                    }
+                    i
+                } else {
+                    index(nvic_priority(nr))
+                };
 
-                __funnel_logger(nvic_prio)
+                logger(index)
             }
         }
     }
 
+    // Ties this logger to the critical section that raised BASEPRI to `basepri`
+    #[cfg(feature = "basepri")]
+    fn masked_by(mut self, basepri: u8) -> Self {
+        self.basepri = basepri;
+        self
+    }
+
+    // Whether the running priority is still high enough to use the ring buffer this logger was
+    // resolved to. A logger resolved with a raised BASEPRI must not outlive its critical section:
+    // once BASEPRI is lowered, the interrupts of its priority level can preempt the writer
+    #[cfg(feature = "basepri")]
+    fn is_masked(&self) -> bool {
+        if self.basepri == 0 {
+            return true;
+        }
+
+        // NOTE lower values mean higher priorities and a BASEPRI of `0` doesn't mask anything
+        let basepri = basepri();
+        basepri != 0 && basepri <= self.basepri
+    }
+
+    /// Forgets the loggers cached by the `cache` option of the `funnel!` macro
+    ///
+    /// This must be called after changing the priority of an interrupt that logs. Call it from
+    /// thread mode (e.g. `main`) or with the interrupts that log masked.
+    pub fn invalidate_cache() {
+        if cfg!(not(cortex_m)) {
+            return;
+        }
+
+        extern "Rust" {
+            fn __funnel_cache() -> &'static [AtomicU8];
+        }
+
+        for entry in unsafe { __funnel_cache() } {
+            entry.store(UNRESOLVED, Ordering::Relaxed);
+        }
+    }
+
     // This function is *non*-reentrant but `Logger` is `!Sync` so each `Logger`s is constrained to
     // a single priority level (therefore no preemption / overlap can occur on any single `Logger`
-    // instance)
-    fn log(&self, s: &str) -> Result<(), ()> {
-        unsafe {
-            // NOTE we use `UnsafeCell` instead of `AtomicUsize` because we want the unique
-            // reference (`&mut-`) semantics; this logger has exclusive access to the `write`
-            // pointer
-            let write = &mut *self.inner.write.get();
-            let buffer = &mut *self.inner.buffer.get();
+    // instance). The shared ring buffer is the exception; see `append_reserved`
+    fn log(&self, input: &[u8]) -> Result<(), ()> {
+        #[cfg(feature = "basepri")]
+        {
+            if !self.is_masked() {
+                return Err(());
+            }
+        }
 
-            let input = s.as_bytes();
+        extern "Rust" {
+            // NOTE the expansion of `funnel!` defines this function; it calls `append_shared` if
+            // the `shared` option was used or `append` otherwise, and then runs the hooks selected
+            // by the `notify` and `watermark` options, if any
+            fn __funnel_log(logger: &Logger, input: &[u8]) -> Result<(), ()>;
+        }
 
-            let blen = buffer.len();
+        if cfg!(cortex_m) {
+            unsafe { __funnel_log(self, input) }
+        } else {
+            self.append_shared(input).map(drop).ok_or(())
+        }
+    }
+
+    /// IMPLEMENTATION DETAIL; DO NOT USE
+    // Writes `input` into the ring buffer of a single priority level; returns the fill level of the
+    // ring buffer before and after the write, in bytes, or `None` if `input` doesn't fit
+    #[doc(hidden)]
+    pub fn append(&self, input: &[u8]) -> Option<(usize, usize)> {
+        unsafe {
+            let blen = self.inner.len();
             let ilen = input.len();
 
-            if ilen > blen {
+            // NOTE `ilen > blen` alone doesn't rule out `blen == 0` when `input` is empty
+            if blen == 0 || ilen > blen {
                 // early exit to hint the optimizer that `blen` can't be `0`
-                return Err(());
+                return None;
             }
 
-            // NOTE we use `UnsafeCell` instead of `AtomicUsize` because we want this operation to
-            // return the same value when calling `log` consecutively
-            let read = *self.inner.read.get();
+            // NOTE this logger is the only one that modifies `write`
+            let write = self.inner.write.load(Ordering::Relaxed);
+            let read = self.inner.read.load(Ordering::Relaxed);
+            atomic::compiler_fence(Ordering::Acquire); // ▼
+            let used = self.inner.distance(read, write);
 
-            if blen >= ilen + (*write).wrapping_sub(read) {
-                // FIXME (?) this is *not* always optimized to a right shift (`lsr`) when `blen` is
-                // a power of 2 -- instead we get an `udiv` which is slower (?).
-                let w = *write % blen;
+            // NOTE `blen >= ilen + used` could overflow when the pointers are garbage (see
+            // `validate`); `blen - ilen` can't because `ilen <= blen`
+            if used <= blen - ilen {
+                self.inner.copy_from(write, input);
 
-                // NOTE we use `ptr::copy_nonoverlapping` instead of `copy_from_slice` to avoid
-                // panicking branches
-                if w + ilen > blen {
-                    // two memcpy-s
-                    let mid = blen - w;
-                    // buffer[w..].copy_from_slice(&input[..mid]);
-                    ptr::copy_nonoverlapping(input.as_ptr(), buffer.as_mut_ptr().add(w), mid);
-                    // buffer[..ilen - mid].copy_from_slice(&input[mid..]);
-                    ptr::copy_nonoverlapping(
-                        input.as_ptr().add(mid),
-                        buffer.as_mut_ptr(),
-                        ilen - mid,
-                    );
-                } else {
-                    // single memcpy
-                    // buffer[w..w + ilen].copy_from_slice(&input);
-                    ptr::copy_nonoverlapping(input.as_ptr(), buffer.as_mut_ptr().add(w), ilen);
+                atomic::compiler_fence(Ordering::Release); // ▲
+                self.inner
+                    .write
+                    .store(self.inner.advance(write, ilen), Ordering::Relaxed);
+
+                let level = used + ilen;
+                if level > self.inner.hwm.load(Ordering::Relaxed) {
+                    self.inner.hwm.store(level, Ordering::Relaxed);
                 }
 
-                *write = (*write).wrapping_add(ilen);
+                #[cfg(feature = "async")]
+                WAKER.wake();
 
-                Ok(())
+                Some((used, level))
             } else {
-                Err(())
+                None
+            }
+        }
+    }
+
+    /// IMPLEMENTATION DETAIL; DO NOT USE
+    // The capacity of the ring buffer, in bytes
+    #[doc(hidden)]
+    pub fn capacity(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// IMPLEMENTATION DETAIL; DO NOT USE
+    // Like `append` but this logger may also write into the shared ring buffer
+    #[doc(hidden)]
+    pub fn append_shared(&self, input: &[u8]) -> Option<(usize, usize)> {
+        if let Some(reserve) = self.inner.reserve {
+            self.append_reserved(reserve, input)
+        } else {
+            self.append(input)
+        }
+    }
+
+    // Writes into the shared ring buffer, which several priority levels write into
+    //
+    // A writer first reserves space by advancing `reserve`, then copies `input` into that space
+    // and finally publishes it to the `Drain` by advancing `write`. As preemption is strictly
+    // nested the writers that preempt a writer finish before it so only the outermost writer --
+    // the one whose reservation started where `write` was -- advances `write`, up to the end of all
+    // the reservations made so far; these include the reservations of the writers that preempted
+    // it.
+    // Each call is atomic but the calls of a single message (e.g. `uwriteln!`) may be interleaved
+    // with the calls of the writers that preempt it.
+    fn append_reserved(&self, reserve: &AtomicUsize, input: &[u8]) -> Option<(usize, usize)> {
+        unsafe {
+            let blen = self.inner.len();
+            let ilen = input.len();
+
+            // NOTE `ilen > blen` alone doesn't rule out `blen == 0` when `input` is empty
+            if blen == 0 || ilen > blen {
+                // early exit to hint the optimizer that `blen` can't be `0`
+                return None;
+            }
+
+            let mut used = 0;
+            let mut outermost = false;
+            // NOTE the update fails, and the closure runs again, if another writer reserved space
+            // in the meantime; this makes the `write` load consistent with the `reserve` value
+            let start = fetch_update(reserve, |start| {
+                let read = self.inner.read.load(Ordering::Relaxed);
+                let write = self.inner.write.load(Ordering::Relaxed);
+                preemption_point!();
+
+                used = self.inner.distance(read, start);
+                outermost = write == start;
+
+                // NOTE see `append`
+                if used <= blen - ilen {
+                    Some(self.inner.advance(start, ilen))
+                } else {
+                    None
+                }
+            })
+            .ok()?;
+            atomic::compiler_fence(Ordering::Acquire); // ▼
+            preemption_point!();
+
+            self.inner.copy_from(start, input);
+
+            preemption_point!();
+            atomic::compiler_fence(Ordering::Release); // ▲
+            if outermost {
+                loop {
+                    // NOTE the update fails if a writer that preempted us published its data, so
+                    // `write` never moves backwards
+                    let mut end = start;
+                    let _ = fetch_update(&self.inner.write, |_| {
+                        end = reserve.load(Ordering::Relaxed);
+                        preemption_point!();
+                        Some(end)
+                    });
+                    preemption_point!();
+
+                    // a writer that preempted us after the update found no pending reservation so
+                    // it publishes its own data; one that preempted us during the update didn't
+                    if reserve.load(Ordering::Relaxed) == end {
+                        break;
+                    }
+                }
             }
+
+            let level = used + ilen;
+            let _ = fetch_update(&self.inner.hwm, |hwm| {
+                if level > hwm {
+                    Some(level)
+                } else {
+                    None
+                }
+            });
+
+            #[cfg(feature = "async")]
+            WAKER.wake();
+
+            Some((used, level))
         }
     }
 }
@@ -346,21 +1604,171 @@ impl uWrite for Logger {
     type Error = ();
 
     fn write_str(&mut self, s: &str) -> Result<(), ()> {
-        self.log(s)
+        self.log(s.as_bytes())
+    }
+}
+
+impl fmt::Write for Logger {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.log(s.as_bytes()).map_err(|_| fmt::Error)
+    }
+}
+
+/// IMPLEMENTATION DETAIL; DO NOT USE
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _flog {
+    ($($tt:tt)*) => {{
+        if let Some(mut logger) = $crate::Logger::get() {
+            let mut tagged = $crate::tag(&mut logger);
+            $crate::uwriteln!(tagged, $($tt)*)
+        } else {
+            Ok(())
+        }
+    }};
+}
+
+/// IMPLEMENTATION DETAIL; DO NOT USE
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _flog_fmt {
+    ($($tt:tt)*) => {{
+        if let Some(mut logger) = $crate::Logger::get() {
+            $crate::_FmtWrite::write_fmt(
+                &mut $crate::tag(&mut logger),
+                format_args!("{}\n", format_args!($($tt)*)),
+            )
+            .map_err(|_| ())
+        } else {
+            Ok(())
+        }
+    }};
+}
+
+/// IMPLEMENTATION DETAIL; DO NOT USE
+// Prefixes a text record with the number of the exception that logs it, if the "exception_tag"
+// feature is enabled
+#[doc(hidden)]
+pub fn tag(logger: &mut Logger) -> Tagged<'_> {
+    Tagged {
+        logger,
+        #[cfg(feature = "exception_tag")]
+        pending: true,
+    }
+}
+
+/// IMPLEMENTATION DETAIL; DO NOT USE
+// A `Logger` that writes the tag of a text record together with the first bytes of the record, in
+// a single append, so a record that doesn't fit never leaves a bare tag in the ring buffer
+#[doc(hidden)]
+pub struct Tagged<'a> {
+    logger: &'a mut Logger,
+    #[cfg(feature = "exception_tag")]
+    pending: bool,
+}
+
+impl Tagged<'_> {
+    fn log(&mut self, input: &[u8]) -> Result<(), ()> {
+        #[cfg(feature = "exception_tag")]
+        {
+            if self.pending {
+                self.pending = false;
+
+                // "[511] " is the longest tag
+                let mut buf = [0; 32];
+                let mut len = 0;
+                buf[len] = b'[';
+                len += 1;
+                let nr = exception();
+                let mut div = 100;
+                while div > 1 && nr < div {
+                    div /= 10;
+                }
+                while div > 0 {
+                    buf[len] = b'0' + (nr / div % 10) as u8;
+                    len += 1;
+                    div /= 10;
+                }
+                buf[len..len + 2].copy_from_slice(b"] ");
+                len += 2;
+
+                let n = input.len().min(buf.len() - len);
+                buf[len..len + n].copy_from_slice(&input[..n]);
+                self.logger.log(&buf[..len + n])?;
+
+                let rest = &input[n..];
+                return if rest.is_empty() {
+                    Ok(())
+                } else {
+                    self.logger.log(rest)
+                };
+            }
+        }
+
+        self.logger.log(input)
+    }
+}
+
+impl uWrite for Tagged<'_> {
+    type Error = ();
+
+    fn write_str(&mut self, s: &str) -> Result<(), ()> {
+        self.log(s.as_bytes())
+    }
+}
+
+impl fmt::Write for Tagged<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.log(s.as_bytes()).map_err(|_| fmt::Error)
     }
 }
 
 /// IMPLEMENTATION DETAIL; DO NOT USE
+// Used by the `watermark` option of the `funnel!` macro. Returns `true` if a write took the fill
+// level of a ring buffer from below `percent`% of its `capacity` to or above it
+//
+// NOTE the threshold is `capacity * percent / 100` but we avoid the division, which is slow (or
+// not available in hardware, e.g. ARMv6-M)
 #[doc(hidden)]
-#[macro_export]
-macro_rules! _flog {
-    ($($tt:tt)*) => {{
-        if let Some(mut logger) = $crate::Logger::get() {
-            $crate::uwriteln!(logger, $($tt)*)
-        } else {
-            Ok(())
+#[inline(always)]
+pub fn crossed_watermark(before: usize, after: usize, capacity: usize, percent: usize) -> bool {
+    before * 100 < capacity * percent && after * 100 >= capacity * percent
+}
+
+/// IMPLEMENTATION DETAIL; DO NOT USE
+// The exception the `notify` option of the `funnel!` macro pends after each write
+#[doc(hidden)]
+#[derive(Clone, Copy)]
+pub enum Notify {
+    PendSV,
+    // a device interrupt, by number
+    Interrupt(u16),
+}
+
+impl Notify {
+    // The exception number (VECTACTIVE) of the handler the drains may run in
+    #[doc(hidden)]
+    pub const fn exception(self) -> u16 {
+        match self {
+            Notify::PendSV => 14,
+            Notify::Interrupt(nr) => nr + 16,
         }
-    }};
+    }
+
+    #[doc(hidden)]
+    pub fn pend(self) {
+        if cfg!(cortex_m) {
+            unsafe {
+                match self {
+                    // PENDSVSET
+                    Notify::PendSV => SCB_ICSR.write_volatile(1 << 28),
+                    Notify::Interrupt(nr) => NVIC_ISPR
+                        .add(usize::from(nr >> 5))
+                        .write_volatile(1 << (nr & 0x1f)),
+                }
+            }
+        }
+    }
 }
 
 /// IMPLEMENTATION DETAIL; DO NOT USE
@@ -535,17 +1943,400 @@ macro_rules! trace {
     }}
 }
 
+/// Logs a string at the 'Error' logging level using `core::fmt`
+///
+/// Syntax matches `writeln!` minus the first argument. Unlike `error!` this macro accepts any type
+/// that implements `core::fmt::Display` or `core::fmt::Debug`, at the cost of more code and slower
+/// formatting.
+///
+/// NOTE a newline is always appended at the end
+#[macro_export]
+macro_rules! error_fmt {
+    ($($tt:tt)*) => {{
+        if $crate::is_enabled($crate::Level::Error) {
+            $crate::_flog_fmt!($($tt)*)
+        } else {
+            Ok(())
+        }
+    }}
+}
+
+/// Logs a string at the 'Warn' logging level using `core::fmt`
+///
+/// Syntax matches `writeln!` minus the first argument. Unlike `warn!` this macro accepts any type
+/// that implements `core::fmt::Display` or `core::fmt::Debug`, at the cost of more code and slower
+/// formatting.
+///
+/// NOTE a newline is always appended at the end
+#[macro_export]
+macro_rules! warn_fmt {
+    ($($tt:tt)*) => {{
+        if $crate::is_enabled($crate::Level::Warn) {
+            $crate::_flog_fmt!($($tt)*)
+        } else {
+            Ok(())
+        }
+    }}
+}
+
+/// Logs a string at the 'Info' logging level using `core::fmt`
+///
+/// Syntax matches `writeln!` minus the first argument. Unlike `info!` this macro accepts any type
+/// that implements `core::fmt::Display` or `core::fmt::Debug`, at the cost of more code and slower
+/// formatting.
+///
+/// NOTE a newline is always appended at the end
+#[macro_export]
+macro_rules! info_fmt {
+    ($($tt:tt)*) => {{
+        if $crate::is_enabled($crate::Level::Info) {
+            $crate::_flog_fmt!($($tt)*)
+        } else {
+            Ok(())
+        }
+    }}
+}
+
+/// Logs a string at the 'Debug' logging level using `core::fmt`
+///
+/// Syntax matches `writeln!` minus the first argument. Unlike `debug!` this macro accepts any type
+/// that implements `core::fmt::Display` or `core::fmt::Debug`, at the cost of more code and slower
+/// formatting.
+///
+/// NOTE a newline is always appended at the end
+#[macro_export]
+macro_rules! debug_fmt {
+    ($($tt:tt)*) => {{
+        if $crate::is_enabled($crate::Level::Debug) {
+            $crate::_flog_fmt!($($tt)*)
+        } else {
+            Ok(())
+        }
+    }}
+}
+
+/// Logs a string at the 'Trace' logging level using `core::fmt`
+///
+/// Syntax matches `writeln!` minus the first argument. Unlike `trace!` this macro accepts any type
+/// that implements `core::fmt::Display` or `core::fmt::Debug`, at the cost of more code and slower
+/// formatting.
+///
+/// NOTE a newline is always appended at the end
+#[macro_export]
+macro_rules! trace_fmt {
+    ($($tt:tt)*) => {{
+        if $crate::is_enabled($crate::Level::Trace) {
+            $crate::_flog_fmt!($($tt)*)
+        } else {
+            Ok(())
+        }
+    }}
+}
+
+/// Description of the ring buffers declared using the `funnel!` macro
+pub struct Config {
+    /// The `NVIC_PRIO_BITS` value passed to the `funnel!` macro
+    pub nvic_prio_bits: u8,
+
+    /// The `prigroup` option of the `funnel!` macro; `0` if it was not used
+    pub prigroup: u8,
+
+    /// The ring buffers, highest priority first (same order as `Drains`)
+    pub buffers: &'static [Buffer],
+
+    /// Static memory, in bytes, used by all the ring buffers, including the `HardFault` and shared
+    /// ones
+    pub total_bytes: usize,
+}
+
+/// Description of a single ring buffer
+pub struct Buffer {
+    /// Logical priority
+    pub priority: u8,
+
+    /// Priority as stored in the NVIC (`NVIC_IPR`) registers
+    pub nvic_priority: u8,
+
+    /// Name of the channel; `"log"` unless declared otherwise
+    pub channel: &'static str,
+
+    /// Capacity of the ring buffer, in bytes
+    pub size: usize,
+
+    /// Static memory, in bytes, used by the ring buffer and its bookkeeping fields
+    pub bytes: usize,
+}
+
+impl Config {
+    /// Returns the configuration declared using the `funnel!` macro
+    pub fn get() -> &'static Self {
+        if cfg!(not(cortex_m)) {
+            return &EMPTY;
+        }
+
+        // NOTE the expansion of `funnel!` declares this function
+        extern "Rust" {
+            fn __funnel_config() -> &'static Config;
+        }
+
+        unsafe { __funnel_config() }
+    }
+}
+
+static EMPTY: Config = Config {
+    nvic_prio_bits: 0,
+    prigroup: 0,
+    buffers: &[],
+    total_bytes: 0,
+};
+
+/// The result of `self_check`
+pub struct SelfCheck {
+    /// Number of priority bits implemented by the NVIC, as measured at runtime
+    ///
+    /// This should be equal to the `NVIC_PRIO_BITS` value passed to the `funnel!` macro (see
+    /// `Config::nvic_prio_bits`).
+    pub nvic_prio_bits: u8,
+
+    /// The PRIGROUP field of the AIRCR register
+    ///
+    /// This should be equal to the `prigroup` option of the `funnel!` macro (see
+    /// `Config::prigroup`), or leave the same number of group priority bits (see `is_ok`).
+    pub prigroup: u8,
+
+    // one bit per interrupt; the NVIC supports up to 496 interrupts
+    uncovered: [u32; 16],
+}
+
+impl SelfCheck {
+    /// Returns `true` if no problem was found
+    ///
+    /// Only the number of group priority bits, which follows from the number of priority bits and
+    /// PRIGROUP, selects a logger so, for example, a PRIGROUP of 3 with 4 priority bits routes
+    /// records like the default PRIGROUP of 0 does.
+    pub fn is_ok(&self) -> bool {
+        let config = Config::get();
+
+        group_bits(self.nvic_prio_bits, self.prigroup)
+            == group_bits(config.nvic_prio_bits, config.prigroup)
+            && self.uncovered.iter().all(|word| *word == 0)
+    }
+
+    /// The enabled interrupts whose priority level has no logger, by interrupt number
+    pub fn uncovered(&self) -> impl Iterator<Item = u16> + '_ {
+        (0..self.uncovered.len() as u16 * 32)
+            .filter(move |nr| self.uncovered[usize::from(nr / 32)] & (1 << (nr % 32)) != 0)
+    }
+}
+
+// The number of group (preemption) priority bits; the `funnel!` macro computes the same value
+fn group_bits(nvic_prio_bits: u8, prigroup: u8) -> u8 {
+    cmp::min(nvic_prio_bits, 7u8.saturating_sub(prigroup))
+}
+
+/// Checks the `funnel!` declaration against the NVIC configuration
+///
+/// This measures the number of priority bits the NVIC implements, reads the priority grouping and
+/// looks for enabled interrupts whose priority level has no logger; interrupts that fall back to
+/// the shared ring buffer are not reported.
+pub fn self_check() -> SelfCheck {
+    let config = Config::get();
+    let mut check = SelfCheck {
+        nvic_prio_bits: config.nvic_prio_bits,
+        prigroup: config.prigroup,
+        uncovered: [0; 16],
+    };
+
+    if cfg!(not(cortex_m)) {
+        return check;
+    }
+
+    extern "Rust" {
+        // NOTE see `Logger::lookup`
+        fn __funnel_drains() -> &'static [Drain];
+
+        fn __funnel_index(nvic_prio: u8) -> u8;
+
+        fn __funnel_shared() -> Option<Ring>;
+    }
+
+    unsafe {
+        // the NVIC ignores writes to the priority bits it doesn't implement so we write all ones to
+        // the priority of interrupt #0 and read it back
+        // NOTE IPR is not byte addressable on ARMv6-M so we modify the whole word (interrupts #0 to
+        // #3) and restore it afterwards; interrupts are masked so no handler sees the change
+        let implemented = interrupt_free(|| {
+            let old = NVIC_IPR.read_volatile();
+            NVIC_IPR.write_volatile(old | 0xff);
+            let implemented = NVIC_IPR.read_volatile() as u8;
+            NVIC_IPR.write_volatile(old);
+            implemented
+        });
+        check.nvic_prio_bits = implemented.leading_ones() as u8;
+        check.prigroup = ((SCB_AIRCR.read_volatile() >> 8) & 0b111) as u8;
+
+        if __funnel_shared().is_some() {
+            // all the interrupts have a logger
+            return check;
+        }
+
+        // number of ISER registers; ARMv6-M supports at most 32 interrupts
+        #[cfg(armv7m)]
+        let words = ((ICTR.read_volatile() & 0xf) + 1) as usize;
+        #[cfg(not(armv7m))]
+        let words = 1;
+
+        let drains = __funnel_drains().len();
+        for (i, uncovered) in check.uncovered.iter_mut().enumerate().take(words) {
+            let enabled = NVIC_ISER.add(i).read_volatile();
+
+            for bit in 0..32 {
+                let nr = (i * 32 + bit) as u16;
+
+                if enabled & (1 << bit) != 0
+                    && usize::from(__funnel_index(nvic_priority(nr))) >= drains
+                {
+                    *uncovered |= 1 << bit;
+                }
+            }
+        }
+    }
+
+    check
+}
+
+/// The drain endpoints of all the ring buffers
+///
+/// This is a singleton: `Drains::take` returns it only once so there's a single owner of each
+/// `Drain`. It dereferences to the drain of each ring buffer, highest priority first.
+// NOTE: `Send` but NOT `Sync`; it can be moved to another context (e.g. an interrupt handler) but
+// two contexts can't share it
+pub struct Drains {
+    drains: &'static [Drain],
+    hardfault: Option<Drain>,
+    shared: Option<Drain>,
+}
+
+// NOTE `&Drain` is not `Send` so this is not auto-implemented
+unsafe impl Send for Drains {}
+
+impl Drains {
+    /// Returns the drains, but only the first time it's called
+    ///
+    /// This function resets the ring buffers that were placed in linker sections not initialized at
+    /// boot (e.g. `.uninit`) and that hold garbage so it should be called before unmasking
+    /// interrupts.
+    pub fn take() -> Option<Self> {
+        static TAKEN: AtomicBool = AtomicBool::new(false);
+
+        // NOTE ARMv6-M has no atomic swap so we mask interrupts to make this load-store sequence
+        // atomic
+        let taken = interrupt_free(|| {
+            let taken = TAKEN.load(Ordering::Relaxed);
+            TAKEN.store(true, Ordering::Relaxed);
+            taken
+        });
+
+        if taken {
+            None
+        } else {
+            Some(unsafe { Self::steal() })
+        }
+    }
+
+    /// Returns the drains regardless of whether they have already been taken
+    ///
+    /// # Safety
+    ///
+    /// Two contexts that read from the same `Drain` at the same time corrupt its ring buffer
+    pub unsafe fn steal() -> Self {
+        Drains {
+            drains: Drain::get_all(),
+            hardfault: Drain::hardfault(),
+            shared: Drain::shared(),
+        }
+    }
+
+    /// The drain endpoint of the `HardFault` ring buffer, if one was declared
+    ///
+    /// This ring buffer is not part of the drains `Drains` dereferences to.
+    pub fn hardfault(&self) -> Option<&Drain> {
+        self.hardfault.as_ref()
+    }
+
+    /// The drain endpoint of the shared ring buffer, if the `shared` option was used
+    ///
+    /// This ring buffer is not part of the drains `Drains` dereferences to.
+    pub fn shared(&self) -> Option<&Drain> {
+        self.shared.as_ref()
+    }
+
+    /// The drains of the given `channel`, highest priority first
+    pub fn channel<C>(&self) -> impl Iterator<Item = &Drain>
+    where
+        C: Channel,
+    {
+        self.drains
+            .iter()
+            .filter(|drain| drain.channel() == Some(C::NAME))
+    }
+}
+
+impl Deref for Drains {
+    type Target = [Drain];
+
+    fn deref(&self) -> &[Drain] {
+        self.drains
+    }
+}
+
 /// A drain retrieves the data written into a `Logger`
-// NOTE: NOT `Sync` or `Send`
+// NOTE: NOT `Clone`, `Copy` or `Sync`; see `Drains`
 #[repr(transparent)]
-#[derive(Clone, Copy)]
 pub struct Drain {
-    inner: &'static Inner<[u8]>,
+    inner: Ring,
+    _not_sync: PhantomData<*const ()>,
 }
 
+unsafe impl Send for Drain {}
+
+// `Drain`, `Drains` and `Logger` must not be `Sync`: two contexts reading from, or writing to, the
+// same ring buffer at the same time corrupt it. `Logger` must not be `Send` either: moving it to
+// another priority level gives its ring buffer a second writer. This fails to compile (the `_` is
+// ambiguous) if any of them is `Sync`, or if `Logger` is `Send`
+const _: fn() = || {
+    trait AmbiguousIfSync<A> {
+        fn some_item() {}
+    }
+
+    impl<T: ?Sized> AmbiguousIfSync<()> for T {}
+    impl<T: ?Sized + Sync> AmbiguousIfSync<u8> for T {}
+
+    trait AmbiguousIfSend<A> {
+        fn some_item() {}
+    }
+
+    impl<T: ?Sized> AmbiguousIfSend<()> for T {}
+    impl<T: ?Sized + Send> AmbiguousIfSend<u8> for T {}
+
+    let _ = <Drain as AmbiguousIfSync<_>>::some_item;
+    let _ = <Drains as AmbiguousIfSync<_>>::some_item;
+    let _ = <Logger as AmbiguousIfSync<_>>::some_item;
+    let _ = <Logger as AmbiguousIfSend<_>>::some_item;
+};
+
 impl Drain {
-    /// The drain endpoint of each ring buffer, highest priority first
-    pub fn get_all() -> &'static [Self] {
+    fn new(inner: Ring) -> Self {
+        Drain {
+            inner,
+            _not_sync: PhantomData,
+        }
+    }
+
+    // The drain endpoint of each ring buffer, highest priority first
+    //
+    // NOTE the first call validates the ring buffers
+    fn get_all() -> &'static [Self] {
         if cfg!(not(cortex_m)) {
             return &[];
         }
@@ -557,52 +2348,204 @@ impl Drain {
         }
 
         // NOTE The expansion of `funnel!` declares `__funnel_drains` as a function with signature
-        // `fn() -> &'static [&'static Inner<[u8]>]` so here we are implicitly transmuting `&'static
-        // Inner<[u8]>` into `Drain` but this should be fine because they are equivalent due to
-        // `#[repr(transparent)]`
+        // `fn() -> &'static [Ring]` so here we are implicitly transmuting `Ring` into `Drain` but
+        // this should be fine because they are equivalent due to `#[repr(transparent)]`
+        // (`PhantomData` is zero sized)
         extern "Rust" {
             fn __funnel_drains() -> &'static [Drain];
+
+            fn __funnel_shared() -> Option<Ring>;
+        }
+
+        let drains = unsafe { __funnel_drains() };
+
+        // NOTE this static variable lives in `.bss` so it's cleared on every boot
+        static VALIDATED: AtomicBool = AtomicBool::new(false);
+
+        if !VALIDATED.load(Ordering::Relaxed) {
+            for drain in drains {
+                drain.inner.validate();
+            }
+
+            if let Some(drain) = Self::hardfault() {
+                drain.inner.validate();
+            }
+
+            if let Some(shared) = unsafe { __funnel_shared() } {
+                shared.validate();
+                // NOTE there are no reservations in progress; no `Logger` can use the ring buffer
+                // before its drain is taken
+                if let Some(reserve) = shared.reserve {
+                    reserve.store(shared.write.load(Ordering::Relaxed), Ordering::Relaxed);
+                }
+            }
+
+            VALIDATED.store(true, Ordering::Relaxed);
+        }
+
+        drains
+    }
+
+    // The drain endpoint of the `HardFault` ring buffer, if one was declared; call `get_all` before
+    // using it so the ring buffer is validated
+    fn hardfault() -> Option<Self> {
+        if cfg!(not(cortex_m)) {
+            return None;
+        }
+
+        if (cfg!(debug_assertions) && cfg!(feature = "max_level_off"))
+            || cfg!(feature = "release_max_level_off")
+        {
+            return None;
+        }
+
+        // NOTE the expansion of `funnel!` declares this function
+        extern "Rust" {
+            fn __funnel_hardfault() -> Option<Ring>;
         }
 
-        unsafe { __funnel_drains() }
+        unsafe { __funnel_hardfault() }.map(Drain::new)
+    }
+
+    // The drain endpoint of the shared ring buffer, if one was declared; call `get_all` before
+    // using it so the ring buffer is validated
+    fn shared() -> Option<Self> {
+        if cfg!(not(cortex_m)) {
+            return None;
+        }
+
+        if (cfg!(debug_assertions) && cfg!(feature = "max_level_off"))
+            || cfg!(feature = "release_max_level_off")
+        {
+            return None;
+        }
+
+        // NOTE the expansion of `funnel!` declares this function
+        extern "Rust" {
+            fn __funnel_shared() -> Option<Ring>;
+        }
+
+        unsafe { __funnel_shared() }.map(Drain::new)
+    }
+
+    /// Returns a future that resolves once any of the ring buffers has data to drain
+    ///
+    /// Only a single task should await this future at any given time; polling it from a second
+    /// task replaces the waker registered by the first one.
+    ///
+    /// The future must be polled from thread mode, i.e. by an executor that runs in `main`. Polling
+    /// it from an interrupt handler, e.g. by an executor that runs tasks in an interrupt, panics.
+    #[cfg(feature = "async")]
+    pub fn readable() -> Readable {
+        Readable { _private: () }
+    }
+
+    /// Returns the logical priority of the `Logger` ring buffer
+    ///
+    /// This returns `None` for the `HardFault` and shared ring buffers, which have no configurable
+    /// priority.
+    pub fn priority(&self) -> Option<u8> {
+        self.buffer().map(|buffer| buffer.priority)
+    }
+
+    /// Returns the NVIC priority of the `Logger` ring buffer
+    ///
+    /// This returns `None` for the `HardFault` and shared ring buffers, which have no configurable
+    /// priority.
+    pub fn nvic_priority(&self) -> Option<u8> {
+        self.buffer().map(|buffer| buffer.nvic_priority)
+    }
+
+    /// Returns the name of the channel of the `Logger` ring buffer
+    ///
+    /// This returns `None` for the `HardFault` and shared ring buffers, which have no channels.
+    pub fn channel(&self) -> Option<&'static str> {
+        self.buffer().map(|buffer| buffer.channel)
+    }
+
+    // The description of the ring buffer in `Config`
+    fn buffer(&self) -> Option<&'static Buffer> {
+        self.inner.config
+    }
+
+    /// Returns the size of the `Logger` ring buffer, in bytes
+    pub fn capacity(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// Returns the number of bytes in the `Logger` ring buffer that have not been drained yet
+    pub fn len(&self) -> usize {
+        let read = self.inner.read.load(Ordering::Relaxed);
+        let write = self.inner.write.load(Ordering::Relaxed);
+
+        self.inner.distance(read, write)
+    }
+
+    /// Returns `true` if the `Logger` ring buffer has no data to drain
+    pub fn is_empty(&self) -> bool {
+        self.inner.write.load(Ordering::Relaxed) == self.inner.read.load(Ordering::Relaxed)
+    }
+
+    /// Returns the maximum number of bytes the `Logger` ring buffer has held at any point
+    ///
+    /// Comparing this value against the size of the ring buffer tells whether the buffer is
+    /// oversized or undersized for the application.
+    pub fn high_water_mark(&self) -> usize {
+        self.inner.hwm.load(Ordering::Relaxed)
+    }
+
+    /// Copies the data that was written into the `Logger` ring buffer before the last reset, and
+    /// that has not been drained yet, into the given buffer
+    ///
+    /// This only returns data when the ring buffer is placed in a linker section that's not
+    /// initialized at boot, e.g. using the `#[persistent]` attribute of the `funnel!` macro. The
+    /// data from the previous boot comes before the data written during this boot so `read` also
+    /// returns it; call this method first to tell them apart.
+    pub fn recover_previous_boot<'b>(&self, buf: &'b mut [u8]) -> &'b [u8] {
+        let previous = self.inner.previous.load(Ordering::Relaxed);
+        let n = cmp::min(buf.len(), previous);
+        self.read(&mut buf[..n])
+    }
+
+    /// Copies the contents of the `Logger` ring buffer into the given buffer
+    ///
+    /// In debug builds this panics if called from an interrupt handler other than the one selected
+    /// using the `notify` option of the `funnel!` macro, or the `HardFault` handler.
+    #[inline]
+    pub fn read<'b>(&self, buf: &'b mut [u8]) -> &'b [u8] {
+        debug_assert!(
+            in_drain_context(),
+            "the drains must be read from thread mode, the `notify` interrupt or the HardFault \
+             handler"
+        );
+
+        self.dequeue(buf)
     }
 
-    /// Copies the contents of the `Logger` ring buffer into the given buffer
     // NOTE this is basically `heapless::spsc::Consumer::dequeue`
-    pub fn read<'b>(&self, buf: &'b mut [u8]) -> &'b [u8] {
+    #[inline(always)]
+    fn dequeue<'b>(&self, buf: &'b mut [u8]) -> &'b [u8] {
         unsafe {
-            // NOTE we use `UnsafeCell` instead of `AtomicUsize` because we want the unique
-            // reference (`&mut-`) semantics; this drain has exclusive access to the `read`
-            // pointer for the duration of this function call
-            let readf = &mut *self.inner.read.get();
-            let writef: *const AtomicUsize = self.inner.write.get() as *const _;
-            let blen = (*self.inner.buffer.get()).len();
-            let p = (*self.inner.buffer.get()).as_ptr();
+            let blen = self.inner.len();
+            let p = self.inner.as_ptr();
 
             // early exit to hint the compiler that `n` is not `0`
             if blen == 0 {
                 return &[];
             }
 
-            let read = *readf;
-            // XXX on paper, this is insta-UB because `Logger::log` has a unique reference
-            // (`&mut-`) to the `write` field and this operation require a shared reference (`&-`)
-            // to the same field. At runtime, this load is atomic (happens in a single instruction)
-            // so any modification done by an interrupt handler (via `Logger::log`) can *not* result
-            // in a data race (e.g. torn read or write). To properly avoid any theoretical UB we
-            // would need to something like `atomic_load(a_raw_pointer_to_write)`, which exist but
-            // it's unstable (`intrinsics::atomic_load`), *plus* `&raw write` (RFC #2582), which has
-            // not been implemented. In practice, as long as this produces a fresh value each time
-            // is called (instead of cached on the stack) we should be fine.
-            let write = (*writef).load(Ordering::Relaxed);
+            // NOTE this drain is the only one that modifies `read`
+            let read = self.inner.read.load(Ordering::Relaxed);
+            preemption_point!();
+            let write = self.inner.write.load(Ordering::Relaxed);
             atomic::compiler_fence(Ordering::Acquire); // ▼
+            preemption_point!();
 
-            if write > read {
+            // NOTE `!=`, not `>`, because the pointers wrap around at `usize::MAX`
+            if write != read {
                 // number of bytes to copy
-                let c = cmp::min(buf.len(), write.wrapping_sub(read));
-                // FIXME (?) this is *not* always optimized to a right shift (`lsr`) when `n` is
-                // a power of 2 -- instead we get an `udiv` which is slower.
-                let r = read % blen;
+                let c = cmp::min(buf.len(), self.inner.distance(read, write));
+                let r = self.inner.index(read);
 
                 // NOTE we use `ptr::copy_nonoverlapping` instead of `copy_from_slice` to avoid
                 // panicking branches
@@ -611,6 +2554,7 @@ impl Drain {
                     let mid = blen - r;
                     // buf[..mid].copy_from_slice(&buffer[r..]);
                     ptr::copy_nonoverlapping(p.add(r), buf.as_mut_ptr(), mid);
+                    preemption_point!();
                     // buf[mid..mid + c].copy_from_slice(&buffer[..c - mid]);
                     ptr::copy_nonoverlapping(p, buf.as_mut_ptr().add(mid), c - mid);
                 } else {
@@ -619,8 +2563,17 @@ impl Drain {
                     ptr::copy_nonoverlapping(p.add(r), buf.as_mut_ptr(), c);
                 }
 
+                preemption_point!();
                 atomic::compiler_fence(Ordering::Release); // ▲
-                *readf = (*readf).wrapping_add(c);
+                self.inner
+                    .read
+                    .store(self.inner.advance(read, c), Ordering::Relaxed);
+                preemption_point!();
+
+                let previous = self.inner.previous.load(Ordering::Relaxed);
+                self.inner
+                    .previous
+                    .store(previous.saturating_sub(c), Ordering::Relaxed);
 
                 // &buf[..c]
                 buf.get_unchecked(..c)
@@ -639,68 +2592,995 @@ impl Iterator for Drain {
     }
 }
 
+impl Iterator for &'_ Drain {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        self.read(&mut [0]).first().cloned()
+    }
+}
+
+// Returns `true` if the caller runs in thread mode, in the interrupt selected using the `notify`
+// option or in the `HardFault` handler
+fn in_drain_context() -> bool {
+    if cfg!(not(cortex_m)) {
+        return true;
+    }
+
+    extern "Rust" {
+        // NOTE the expansion of `funnel!` always defines this function; it returns `0` if the
+        // `notify` option was not used
+        fn __funnel_notify_exception() -> u16;
+    }
+
+    let active = exception();
+    active == 0 || active == 3 || active == unsafe { __funnel_notify_exception() }
+}
+
+// The number of the running exception (VECTACTIVE); `0` in thread mode
+fn exception() -> u16 {
+    if cfg!(not(cortex_m)) {
+        return 0;
+    }
+
+    unsafe { SCB_ICSR.read_volatile() as u16 & 0x1ff }
+}
+
+/// Future returned by `Drain::readable`
+#[cfg(feature = "async")]
+pub struct Readable {
+    _private: (),
+}
+
+#[cfg(feature = "async")]
+impl Future for Readable {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let readable = || {
+            Drain::get_all().iter().any(|drain| !drain.is_empty())
+                || Drain::shared().is_some_and(|drain| !drain.is_empty())
+        };
+
+        if readable() {
+            return Poll::Ready(());
+        }
+
+        // NOTE required for the soundness of `WakerSlot::register`
+        assert!(
+            exception() == 0,
+            "`Drain::readable` must be polled from thread mode"
+        );
+
+        WAKER.register(cx.waker());
+
+        // a `Logger` may have written data after the first check but before the registration
+        if readable() {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+/// The waker of the task awaiting `Drain::readable`
+// NOTE the waker is registered from thread mode (`Readable::poll` checks VECTACTIVE) and woken
+// from interrupt handlers. As interrupt handlers can't be preempted by thread mode it's enough to
+// "unpublish" the waker (`registered = false`) while it's being replaced to avoid data races
+#[cfg(feature = "async")]
+struct WakerSlot {
+    registered: AtomicBool,
+    waker: UnsafeCell<Option<Waker>>,
+}
+
+#[cfg(feature = "async")]
+unsafe impl Sync for WakerSlot {}
+
+#[cfg(feature = "async")]
+static WAKER: WakerSlot = WakerSlot {
+    registered: AtomicBool::new(false),
+    waker: UnsafeCell::new(None),
+};
+
+#[cfg(feature = "async")]
+impl WakerSlot {
+    // NOTE must only be called from thread mode
+    fn register(&self, waker: &Waker) {
+        self.registered.store(false, Ordering::Relaxed);
+        atomic::compiler_fence(Ordering::SeqCst);
+
+        unsafe {
+            let slot = &mut *self.waker.get();
+
+            match slot {
+                Some(w) if w.will_wake(waker) => {}
+                _ => *slot = Some(waker.clone()),
+            }
+        }
+
+        atomic::compiler_fence(Ordering::SeqCst);
+        self.registered.store(true, Ordering::Relaxed);
+    }
+
+    // NOTE this may be called from any priority level; if a higher priority handler preempts this
+    // function it will either see `registered = false` or also wake the task, which is harmless
+    fn wake(&self) {
+        if self.registered.load(Ordering::Relaxed) {
+            self.registered.store(false, Ordering::Relaxed);
+            atomic::compiler_fence(Ordering::SeqCst);
+
+            unsafe {
+                if let Some(waker) = &*self.waker.get() {
+                    waker.wake_by_ref();
+                }
+            }
+        }
+    }
+}
+
+// Compilation errors of the `funnel!` macro. Each `compile_fail` example comes after a variant
+// that compiles so it can't fail for an unrelated reason. NOTE the "panic" feature is excluded
+// because its `#[panic_handler]` conflicts with the one of `std`
+#[cfg(all(doctest, not(feature = "panic")))]
+mod compile_fail {
+    /// The ring buffers must fit in `max_ram`
+    ///
+    /// ```
+    /// funnel::funnel!(NVIC_PRIO_BITS = 3, max_ram = 1024, { 1: 32, 2: 64 });
+    /// # fn main() {}
+    /// ```
+    ///
+    /// ``` compile_fail
+    /// funnel::funnel!(NVIC_PRIO_BITS = 3, max_ram = 64, { 1: 32, 2: 64 });
+    /// # fn main() {}
+    /// ```
+    ///
+    /// The shared ring buffer counts towards the limit
+    ///
+    /// ``` compile_fail
+    /// funnel::funnel!(NVIC_PRIO_BITS = 3, max_ram = 128, shared = 128, { 1: 32 });
+    /// # fn main() {}
+    /// ```
+    struct MaxRam;
+
+    /// With `power_of_two = true` every size must be a power of 2
+    ///
+    /// ```
+    /// funnel::funnel!(NVIC_PRIO_BITS = 3, power_of_two = true, shared = 64, { 1: 32 });
+    /// # fn main() {}
+    /// ```
+    ///
+    /// ``` compile_fail
+    /// funnel::funnel!(NVIC_PRIO_BITS = 3, power_of_two = true, { 1: 48 });
+    /// # fn main() {}
+    /// ```
+    ///
+    /// ``` compile_fail
+    /// funnel::funnel!(NVIC_PRIO_BITS = 3, power_of_two = true, { 1: { log: 32, data: 48 } });
+    /// # fn main() {}
+    /// ```
+    ///
+    /// ``` compile_fail
+    /// funnel::funnel!(NVIC_PRIO_BITS = 3, power_of_two = true, shared = 48, { 1: 32 });
+    /// # fn main() {}
+    /// ```
+    struct PowerOfTwo;
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{Drain, Inner, Logger};
+    use core::{
+        cell::{Cell, RefCell},
+        mem,
+        sync::atomic::Ordering,
+    };
+    use std::{boxed::Box, collections::VecDeque, rc::Rc, thread_local, vec::Vec};
+
+    use proptest::prelude::*;
+
+    use super::{
+        model::{self, Op},
+        Buffer, Channel, Drain, Drains, Inner, Logger, Ring, Shared,
+    };
+
+    // `(point, interrupt)`: runs `interrupt` when preemption point number `point` is reached
+    type Interrupt = Option<(usize, Box<dyn FnOnce()>)>;
+
+    thread_local! {
+        // number of preemption points reached so far
+        static POINTS: Cell<usize> = const { Cell::new(0) };
+        static INTERRUPT: Cell<Interrupt> = const { Cell::new(None) };
+    }
+
+    pub fn preempt() {
+        let point = POINTS.with(|points| {
+            let point = points.get();
+            points.set(point + 1);
+            point
+        });
+
+        if let Some((at, interrupt)) = INTERRUPT.with(|cell| cell.take()) {
+            if at == point {
+                interrupt();
+            } else {
+                INTERRUPT.with(|cell| cell.set(Some((at, interrupt))));
+            }
+        }
+    }
+
+    // Runs `f` with an interrupt that fires at preemption point number `at`; returns whether the
+    // interrupt fired
+    fn with_interrupt(at: usize, interrupt: impl FnOnce() + 'static, f: impl FnOnce()) -> bool {
+        POINTS.with(|points| points.set(0));
+        INTERRUPT.with(|cell| cell.set(Some((at, Box::new(interrupt)))));
+
+        f();
+
+        INTERRUPT.with(|cell| cell.take()).is_none()
+    }
+
+    // An interrupt handler logs while `Drain::read` is in progress; checks that no byte is lost
+    // or duplicated, for every possible preemption point. `start` is the initial value of the
+    // `read` and `write` pointers
+    fn interleaving<const N: usize>(start: usize) {
+        for at in 0.. {
+            // NOTE `Logger` and `Drain` need a `'static` reference; this is freed at the end of
+            // the iteration
+            let raw = Box::into_raw(Box::new(Inner::new([0; N])));
+            let inner = unsafe { Ring::new(&*raw, None) };
+            inner.read.store(start, Ordering::Relaxed);
+            inner.write.store(start, Ordering::Relaxed);
+
+            let logger = Logger::new(inner);
+            let drain = Drain::new(inner);
+
+            // reference model; only successful writes are pushed into it
+            let model = Rc::new(RefCell::new(VecDeque::new()));
+
+            let write = |bytes: &[u8]| {
+                if logger.log(bytes).is_ok() {
+                    model.borrow_mut().extend(bytes);
+                }
+            };
+
+            write(b"Hello");
+
+            let mut drained = Vec::new();
+            let mut drain_all = || {
+                let mut buf = [0; 3];
+                loop {
+                    let bytes = drain.read(&mut buf);
+                    if bytes.is_empty() {
+                        break;
+                    }
+                    drained.extend_from_slice(bytes);
+                }
+            };
+
+            let model_ = model.clone();
+            let fired = with_interrupt(
+                at,
+                move || {
+                    if (Logger::new(inner)).log(b", world!").is_ok() {
+                        model_.borrow_mut().extend(b", world!");
+                    }
+                },
+                &mut drain_all,
+            );
+
+            // the interrupt may have logged during the last, empty, read
+            drain_all();
+
+            let expected = model.borrow().iter().cloned().collect::<Vec<_>>();
+            assert_eq!(drained, expected, "preemption point: {}", at);
+
+            // the ring buffer is empty and the pointers are consistent
+            write(b"!");
+            let mut buf = [0; 8];
+            assert_eq!(drain.read(&mut buf), b"!");
+            assert_eq!(drain.read(&mut buf), b"");
+
+            unsafe { drop(Box::from_raw(raw)) }
+
+            if !fired {
+                // all the preemption points have been exercised
+                break;
+            }
+        }
+    }
+
+    // Two interrupt handlers, the second one preempting the first one, write into the shared ring
+    // buffer while a lower priority context writes into it; checks that each message is published
+    // whole and exactly once, for every possible pair of preemption points. `start` is the initial
+    // value of the pointers
+    fn shared<const N: usize>(start: usize) {
+        fn log(shared: Ring, bytes: &[u8]) {
+            let logger = Logger::new(shared);
+
+            logger.log(bytes).unwrap();
+        }
+
+        for outer in 0.. {
+            let mut outer_fired = false;
+
+            for inner in 0.. {
+                // NOTE freed at the end of the iteration
+                let raw = Box::into_raw(Box::new(Shared::new([0; N])));
+                let shared: &'static Shared<[u8; N]> = unsafe { &*raw };
+                shared.reserve.store(start, Ordering::Relaxed);
+                shared.inner.read.store(start, Ordering::Relaxed);
+                shared.inner.write.store(start, Ordering::Relaxed);
+
+                let inner_fired = Rc::new(Cell::new(false));
+                let inner_fired_ = inner_fired.clone();
+                outer_fired = with_interrupt(
+                    outer,
+                    move || {
+                        let fired = with_interrupt(
+                            inner,
+                            move || log(Ring::shared(shared), b"!"),
+                            || log(Ring::shared(shared), b", world"),
+                        );
+                        inner_fired_.set(fired);
+                    },
+                    || log(Ring::shared(shared), b"Hello"),
+                );
+
+                // all the reservations have been published
+                assert_eq!(
+                    shared.reserve.load(Ordering::Relaxed),
+                    shared.inner.write.load(Ordering::Relaxed)
+                );
+
+                let mut drained = Vec::new();
+                let drain = Drain::new(Ring::shared(shared));
+                drained.extend(&drain);
+
+                // each message is published whole, in any order
+                let permutations: &[&[u8]] = match (outer_fired, inner_fired.get()) {
+                    (false, _) => &[b"Hello"],
+                    (true, false) => &[b"Hello, world", b", worldHello"],
+                    (true, true) => &[
+                        b"Hello, world!",
+                        b"Hello!, world",
+                        b", worldHello!",
+                        b", world!Hello",
+                        b"!Hello, world",
+                        b"!, worldHello",
+                    ],
+                };
+                assert!(
+                    permutations.contains(&&drained[..]),
+                    "preemption points: {} {}: {:?}",
+                    outer,
+                    inner,
+                    std::string::String::from_utf8_lossy(&drained)
+                );
+
+                unsafe { drop(Box::from_raw(raw)) }
+
+                if !inner_fired.get() {
+                    break;
+                }
+            }
+
+            if !outer_fired {
+                // all the preemption points have been exercised
+                break;
+            }
+        }
+    }
+
+    fn op() -> impl Strategy<Value = Op> {
+        prop_oneof![
+            (0..80usize).prop_map(Op::Write),
+            (0..80usize).prop_map(Op::Read)
+        ]
+    }
+
+    fn start() -> impl Strategy<Value = usize> {
+        prop_oneof![
+            Just(0),
+            any::<usize>(),
+            // close to the point where the pointers wrap around
+            (0..128usize).prop_map(|offset| 0usize.wrapping_sub(offset)),
+        ]
+    }
+
+    proptest! {
+        // NOTE Miri's isolation rejects the file the regressions are persisted to, and it's too slow
+        // to run the default number of cases
+        #![proptest_config(if cfg!(miri) {
+            ProptestConfig {
+                cases: 8,
+                failure_persistence: None,
+                ..ProptestConfig::default()
+            }
+        } else {
+            ProptestConfig::default()
+        })]
+
+        #[test]
+        fn model(
+            size in 0..model::SIZES.len(),
+            start in start(),
+            ops in proptest::collection::vec(op(), 0..128),
+        ) {
+            model::check(size, start, &ops);
+        }
+    }
+
+    #[test]
+    fn preempted_read() {
+        interleaving::<8>(0);
+        interleaving::<16>(5);
+        interleaving::<7>(0);
+        interleaving::<10>(3);
+        interleaving::<1>(0);
+    }
+
+    #[test]
+    fn preempted_read_wrap_around() {
+        for offset in 1..=16 {
+            // power of 2 sized buffers wrap around at `usize::MAX`
+            interleaving::<8>(0usize.wrapping_sub(offset));
+            interleaving::<16>(0usize.wrapping_sub(offset));
+
+            // other buffers wrap around earlier
+            interleaving::<7>(super::wrap_point(7) - offset);
+            interleaving::<10>(super::wrap_point(10) - offset);
+        }
+    }
+
+    #[test]
+    fn preempted_shared_write() {
+        shared::<16>(0);
+        shared::<13>(0);
+        shared::<16>(0usize.wrapping_sub(6));
+        shared::<13>(super::wrap_point(13) - 6);
+    }
+
+    #[test]
+    fn self_check() {
+        // NOTE there's no NVIC on the host
+        let check = super::self_check();
+        assert!(check.is_ok());
+        assert_eq!(check.uncovered().next(), None);
+
+        let mut check = check;
+        check.uncovered[1] = 1 << 3;
+        check.uncovered[15] = 1 << 31;
+        assert!(!check.is_ok());
+        assert_eq!(check.uncovered().collect::<Vec<_>>(), [35, 511]);
+
+        // PRIGROUP only matters when it takes group priority bits away
+        assert_eq!(super::group_bits(4, 3), super::group_bits(4, 0));
+        assert_eq!(super::group_bits(4, 4), 3);
+        assert_eq!(super::group_bits(4, 7), 0);
+    }
 
     #[test]
     fn sanity() {
         static INNER: Inner<[u8; 32]> = Inner::new([0; 32]);
 
-        let inner = &INNER;
+        let inner = unsafe { Ring::new(&INNER, None) };
         let m = "Hello, world!";
-        let logger = Logger { inner };
-        logger.log(m).unwrap();
+        let logger = Logger::new(inner);
+        logger.log(m.as_bytes()).unwrap();
         unsafe {
             assert!((*logger.inner.buffer.get()).starts_with(m.as_bytes()));
         }
     }
 
+    #[test]
+    fn channel() {
+        crate::channel!(struct Telemetry = telemetry);
+
+        assert_eq!(<Telemetry as Channel>::NAME, "telemetry");
+
+        // no logger on the host
+        assert!(Logger::get_channel::<Telemetry>().is_none());
+        let drains = unsafe { Drains::steal() };
+        assert!(drains.channel::<Telemetry>().next().is_none());
+    }
+
+    #[test]
+    fn drains_singleton() {
+        assert!(Drains::take().is_some());
+        assert!(Drains::take().is_none());
+    }
+
     #[test]
     fn drain() {
         static INNER: Inner<[u8; 32]> = Inner::new([0; 32]);
 
-        let inner = &INNER;
-        let logger = Logger { inner };
-        let mut drain = Drain { inner };
+        let inner = unsafe { Ring::new(&INNER, None) };
+        let logger = Logger::new(inner);
+        let mut drain = Drain::new(inner);
 
         assert_eq!(drain.next(), None);
 
-        logger.log("A").unwrap();
+        logger.log(b"A").unwrap();
         assert_eq!(drain.next(), Some(b'A'));
         assert_eq!(drain.next(), None);
 
-        logger.log("B").unwrap();
+        logger.log(b"B").unwrap();
         assert_eq!(drain.next(), Some(b'B'));
         assert_eq!(drain.next(), None);
 
-        logger.log("CD").unwrap();
+        logger.log(b"CD").unwrap();
         assert_eq!(drain.next(), Some(b'C'));
         assert_eq!(drain.next(), Some(b'D'));
         assert_eq!(drain.next(), None);
     }
 
+    #[test]
+    fn validate() {
+        static INNER: Inner<[u8; 16]> = Inner::new([0; 16]);
+
+        let inner = unsafe { Ring::new(&INNER, None) };
+        let logger = Logger::new(inner);
+        let mut drain = Drain::new(inner);
+
+        // a fresh header is all zeros so ring buffers can be placed in `.bss`
+        assert_eq!(inner.magic.load(Ordering::Relaxed), 0);
+        assert_eq!(inner.generation.load(Ordering::Relaxed), 0);
+        assert_eq!(inner.crc.load(Ordering::Relaxed), 0);
+
+        // a valid header is left untouched
+        logger.log(b"Hello").unwrap();
+        inner.validate();
+        assert_eq!(drain.high_water_mark(), 5);
+
+        // fake the contents of uninitialized memory
+        inner.magic.store(0xdead_beef, Ordering::Relaxed);
+        inner.validate();
+        assert_eq!(drain.next(), None);
+        assert_eq!(drain.high_water_mark(), 0);
+
+        logger.log(b"Hello").unwrap();
+        inner.write.store(17, Ordering::Relaxed);
+        inner.read.store(0, Ordering::Relaxed);
+        inner.validate();
+        assert_eq!(drain.next(), None);
+
+        let mut buf = [0; 16];
+        logger.log(b"Hello").unwrap();
+        assert_eq!(drain.read(&mut buf), b"Hello");
+    }
+
+    #[test]
+    fn recover_previous_boot() {
+        static INNER: Inner<[u8; 16]> = Inner::new([0; 16]);
+
+        let inner = unsafe { Ring::new(&INNER, None) };
+        let logger = Logger::new(inner);
+        let drain = Drain::new(inner);
+
+        let mut buf = [0; 16];
+
+        // first boot: everything was written during this boot
+        inner.validate();
+        logger.log(b"Hello, ").unwrap();
+        assert_eq!(drain.recover_previous_boot(&mut buf), b"");
+        assert_eq!(drain.read(&mut buf[..2]), b"He");
+        logger.log(b"world!").unwrap();
+
+        // reset, if the ring buffer is not initialized by the runtime
+        inner.validate();
+        logger.log(b"Bye").unwrap();
+        assert_eq!(drain.recover_previous_boot(&mut buf[..3]), b"llo");
+        assert_eq!(drain.recover_previous_boot(&mut buf), b", world!");
+        assert_eq!(drain.recover_previous_boot(&mut buf), b"");
+        assert_eq!(drain.read(&mut buf), b"Bye");
+
+        // `read` also returns the data from the previous boot
+        logger.log(b"Hi").unwrap();
+        inner.validate();
+        assert_eq!(drain.read(&mut buf[..1]), b"H");
+        assert_eq!(drain.recover_previous_boot(&mut buf), b"i");
+        assert_eq!(drain.recover_previous_boot(&mut buf), b"");
+    }
+
+    #[test]
+    fn log_before_validate() {
+        static INNER: Inner<[u8; 10]> = Inner::new([0; 10]);
+
+        let inner = unsafe { Ring::new(&INNER, None) };
+        let logger = Logger::new(inner);
+        let drain = Drain::new(inner);
+
+        // garbage left in a persistent ring buffer; both pointers are past the wrap point
+        for (read, write) in [
+            (usize::MAX, usize::MAX - 3),
+            (usize::MAX - 3, usize::MAX),
+            (3, usize::MAX),
+            (usize::MAX, 3),
+        ] {
+            inner.read.store(read, Ordering::Relaxed);
+            inner.write.store(write, Ordering::Relaxed);
+
+            // NOTE the outcome doesn't matter as long as it doesn't panic (e.g. overflow)
+            let _ = logger.log(b"Hello");
+
+            // `validate` resets the ring buffer
+            inner.validate();
+            logger.log(b"Hello").unwrap();
+            let mut buf = [0; 10];
+            assert_eq!(drain.read(&mut buf), b"Hello");
+        }
+    }
+
+    #[cfg(feature = "panic")]
+    #[test]
+    fn panic_message() {
+        let mut buf = [0; 8];
+        assert_eq!(crate::panic::format(&mut buf, "boom"), b"boom\n");
+
+        // truncated
+        assert_eq!(crate::panic::format(&mut buf, "Hello, world!"), b"Hello, w");
+    }
+
+    #[cfg(all(feature = "defmt", not(feature = "exception_tag")))]
+    #[test]
+    fn defmt_frames() {
+        use crate::defmt::State;
+
+        static INNER: Inner<[u8; 8]> = Inner::new([0; 8]);
+
+        let inner = unsafe { Ring::new(&INNER, None) };
+        let logger = Logger::new(inner);
+        let drain = Drain::new(inner);
+        let mut state = State::new();
+
+        let mut buf = [0; 8];
+        state.start(&logger);
+        state.write(&logger, &[1, 0, 2]);
+        state.end(&logger);
+        // leading delimiter, rzcobs encoded frame, trailing delimiter
+        assert_eq!(drain.read(&mut buf), [0x00, 1, 2, 0x7a, 0x00]);
+
+        // a frame that doesn't fit is reduced to its delimiter
+        state.start(&logger);
+        state.write(&logger, &[1; 10]);
+        state.end(&logger);
+        assert_eq!(drain.read(&mut buf), [0x00]);
+
+        // and doesn't affect the next frame
+        state.start(&logger);
+        state.write(&logger, &[1, 0, 2]);
+        state.end(&logger);
+        assert_eq!(drain.read(&mut buf), [1, 2, 0x7a, 0x00]);
+    }
+
+    #[cfg(feature = "defmt")]
+    #[test]
+    fn defmt_buffer() {
+        use crate::defmt::Buffer;
+
+        let mut buf = Buffer::new();
+        buf.push(&[1, 2, 3]);
+        assert_eq!(buf.as_slice(), [1, 2, 3]);
+        assert!(!buf.truncated);
+
+        // more than the encoder ever emits
+        buf.push(&[4; 64]);
+        assert_eq!(buf.as_slice(), [1, 2, 3]);
+        assert!(buf.truncated);
+
+        // the data that follows a truncation is dropped too
+        buf.push(&[5]);
+        assert_eq!(buf.as_slice(), [1, 2, 3]);
+    }
+
+    #[test]
+    fn crash_dump() {
+        use crate::hardfault::{self, CrashDump};
+
+        let frame = [0, 1, 2, 3, 12, 0xffff_fff9, 0x0800_0400, 0x0100_0000];
+        let status = [0x8200, 0x4000_0000, 0xe000_edf8, 0x2001_0000];
+        let bytes = hardfault::encode(&frame, status);
+
+        assert_eq!(
+            CrashDump::decode(&bytes),
+            Some(CrashDump {
+                r0: 0,
+                r1: 1,
+                r2: 2,
+                r3: 3,
+                r12: 12,
+                lr: 0xffff_fff9,
+                pc: 0x0800_0400,
+                xpsr: 0x0100_0000,
+                cfsr: 0x8200,
+                hfsr: 0x4000_0000,
+                mmfar: 0xe000_edf8,
+                bfar: 0x2001_0000,
+            })
+        );
+
+        // truncated
+        assert_eq!(CrashDump::decode(&bytes[..hardfault::SIZE - 1]), None);
+
+        // unknown version
+        let mut bytes = bytes;
+        bytes[2] += 1;
+        assert_eq!(CrashDump::decode(&bytes), None);
+    }
+
+    #[test]
+    fn high_water_mark() {
+        static INNER: Inner<[u8; 16]> = Inner::new([0; 16]);
+
+        let inner = unsafe { Ring::new(&INNER, None) };
+        let logger = Logger::new(inner);
+        let drain = Drain::new(inner);
+
+        let mut buf = [0; 16];
+        assert_eq!(drain.high_water_mark(), 0);
+
+        logger.log(b"Hello").unwrap();
+        assert_eq!(drain.high_water_mark(), 5);
+
+        // draining doesn't lower the high water mark
+        drain.read(&mut buf);
+        assert_eq!(drain.high_water_mark(), 5);
+
+        logger.log(b"Hi").unwrap();
+        assert_eq!(drain.high_water_mark(), 5);
+
+        logger.log(b", world!").unwrap();
+        assert_eq!(drain.high_water_mark(), 10);
+
+        // failed writes don't change the high water mark
+        logger.log(b"Hello, world!").unwrap_err();
+        assert_eq!(drain.high_water_mark(), 10);
+    }
+
+    #[test]
+    fn crossed_watermark() {
+        use super::crossed_watermark;
+
+        // threshold: 75% of 16 = 12 bytes
+        assert!(crossed_watermark(0, 12, 16, 75));
+        assert!(crossed_watermark(11, 12, 16, 75));
+        assert!(crossed_watermark(11, 13, 16, 75));
+        assert!(!crossed_watermark(0, 11, 16, 75));
+
+        // the threshold is not a whole number of bytes: 75% of 10 = 7.5 bytes
+        assert!(!crossed_watermark(0, 7, 10, 75));
+        assert!(crossed_watermark(7, 8, 10, 75));
+
+        // 100%: only a write that fills the ring buffer crosses it
+        assert!(crossed_watermark(0, 16, 16, 100));
+        assert!(crossed_watermark(15, 16, 16, 100));
+        assert!(!crossed_watermark(0, 15, 16, 100));
+
+        // writes that start at or above the mark don't cross it again
+        assert!(!crossed_watermark(12, 13, 16, 75));
+        assert!(!crossed_watermark(13, 16, 16, 75));
+        assert!(!crossed_watermark(16, 16, 16, 100));
+    }
+
+    #[test]
+    fn notify_exception() {
+        use super::Notify;
+
+        assert_eq!(Notify::PendSV.exception(), 14);
+        assert_eq!(Notify::Interrupt(0).exception(), 16);
+        assert_eq!(Notify::Interrupt(2).exception(), 18);
+        assert_eq!(Notify::Interrupt(479).exception(), 495);
+    }
+
+    #[test]
+    fn metadata() {
+        static INNER: Inner<[u8; 10]> = Inner::new([0; 10]);
+
+        let inner = unsafe { Ring::new(&INNER, None) };
+        let logger = Logger::new(inner);
+        let drain = Drain::new(inner);
+
+        assert_eq!(drain.capacity(), 10);
+        assert!(drain.is_empty());
+
+        let mut buf = [0; 4];
+        for _ in 0..3 {
+            // NOTE the ring buffer wraps around on the third iteration
+            logger.log(b"Hello").unwrap();
+            assert_eq!(drain.len(), 5);
+            assert!(!drain.is_empty());
+
+            drain.read(&mut buf);
+            assert_eq!(drain.len(), 1);
+
+            drain.read(&mut buf);
+            assert_eq!(drain.len(), 0);
+            assert!(drain.is_empty());
+        }
+
+        // not one of the ring buffers declared using `funnel!`
+        assert_eq!(drain.priority(), None);
+        assert_eq!(drain.nvic_priority(), None);
+
+        static INNER2: Inner<[u8; 10]> = Inner::new([0; 10]);
+        static BUFFER: Buffer = Buffer {
+            priority: 2,
+            nvic_priority: 192,
+            channel: "log",
+            size: 10,
+            bytes: mem::size_of::<Inner<[u8; 10]>>(),
+        };
+
+        let drain = Drain::new(unsafe { Ring::new(&INNER2, Some(&BUFFER)) });
+        assert_eq!(drain.priority(), Some(2));
+        assert_eq!(drain.nvic_priority(), Some(192));
+        assert_eq!(drain.channel(), Some("log"));
+    }
+
     #[test]
     fn read() {
         static INNER: Inner<[u8; 16]> = Inner::new([0; 16]);
 
-        let inner = &INNER;
-        let logger = Logger { inner };
-        let drain = Drain { inner };
+        let inner = unsafe { Ring::new(&INNER, None) };
+        let logger = Logger::new(inner);
+        let drain = Drain::new(inner);
 
         let mut buf = [0; 8];
-        logger.log("Hello, world!").unwrap();
+        logger.log(b"Hello, world!").unwrap();
         assert_eq!(drain.read(&mut buf), b"Hello, w");
         assert_eq!(drain.read(&mut buf), b"orld!");
         assert_eq!(drain.read(&mut buf), b"");
 
         // NOTE the ring buffer will wrap around with this operation
-        logger.log("Hello, world!").unwrap();
+        logger.log(b"Hello, world!").unwrap();
         assert_eq!(drain.read(&mut buf), b"Hello, w");
         assert_eq!(drain.read(&mut buf), b"orld!");
         assert_eq!(drain.read(&mut buf), b"");
     }
 
+    #[test]
+    fn fmt_write() {
+        use core::fmt::Write;
+
+        static INNER: Inner<[u8; 16]> = Inner::new([0; 16]);
+
+        let inner = unsafe { Ring::new(&INNER, None) };
+        let mut logger = Logger::new(inner);
+        let drain = Drain::new(inner);
+
+        let mut buf = [0; 16];
+        writeln!(logger, "{} + {} = {:?}", 1, 2, Some(3)).unwrap();
+        assert_eq!(drain.read(&mut buf), b"1 + 2 = Some(3)\n");
+
+        // a full ring buffer is reported as a formatting error
+        assert!(write!(logger, "{:>17}", 0).is_err());
+    }
+
+    #[cfg(feature = "exception_tag")]
+    #[test]
+    fn exception_tag() {
+        static INNER: Inner<[u8; 16]> = Inner::new([0; 16]);
+
+        let inner = unsafe { Ring::new(&INNER, None) };
+        let mut logger = Logger::new(inner);
+        let drain = Drain::new(inner);
+
+        // thread mode
+        let mut buf = [0; 16];
+        ufmt::uwriteln!(super::tag(&mut logger), "{}", "GPIOF").unwrap();
+        assert_eq!(drain.read(&mut buf), b"[0] GPIOF\n");
+
+        // a record that doesn't fit leaves no bare tag behind
+        assert!(ufmt::uwriteln!(super::tag(&mut logger), "{}", "Hello, world!").is_err());
+        assert!(drain.is_empty());
+    }
+
+    #[cfg(all(feature = "defmt", feature = "exception_tag"))]
+    #[test]
+    fn defmt_exception_tag() {
+        use crate::defmt::State;
+
+        static INNER: Inner<[u8; 8]> = Inner::new([0; 8]);
+
+        let inner = unsafe { Ring::new(&INNER, None) };
+        let logger = Logger::new(inner);
+        let drain = Drain::new(inner);
+        let mut state = State::new();
+
+        let mut buf = [0; 8];
+        state.start(&logger);
+        state.write(&logger, &[1, 0, 2]);
+        state.end(&logger);
+        // leading delimiter, rzcobs encoded tag (`0` = thread mode) and frame, trailing delimiter
+        assert_eq!(drain.read(&mut buf), [0x00, 1, 2, 0x75, 0x00]);
+    }
+
+    #[cfg(feature = "log")]
+    #[test]
+    fn log_records() {
+        static INNER: Inner<[u8; 32]> = Inner::new([0; 32]);
+
+        let inner = unsafe { Ring::new(&INNER, None) };
+        let mut logger = Logger::new(inner);
+        let drain = Drain::new(inner);
+
+        let mut buf = [0; 32];
+        crate::log::write(
+            &mut logger,
+            &::log::Record::builder()
+                .level(::log::Level::Info)
+                .args(format_args!("GPIOA {}", 42))
+                .build(),
+        );
+        if cfg!(feature = "exception_tag") {
+            // `0` = thread mode
+            assert_eq!(drain.read(&mut buf), b"[0] INFO GPIOA 42\n");
+        } else {
+            assert_eq!(drain.read(&mut buf), b"INFO GPIOA 42\n");
+        }
+    }
+
+    #[cfg(feature = "log")]
+    #[test]
+    fn log_max_level() {
+        use ::log::LevelFilter;
+
+        use crate::{
+            is_enabled,
+            log::{level_filter, max_level},
+            Level,
+        };
+
+        assert_eq!(level_filter(None), LevelFilter::Off);
+        assert_eq!(level_filter(Some(Level::Error)), LevelFilter::Error);
+        assert_eq!(level_filter(Some(Level::Warn)), LevelFilter::Warn);
+        assert_eq!(level_filter(Some(Level::Info)), LevelFilter::Info);
+        assert_eq!(level_filter(Some(Level::Debug)), LevelFilter::Debug);
+        assert_eq!(level_filter(Some(Level::Trace)), LevelFilter::Trace);
+
+        // `log` records are filtered like the records of this crate's macros
+        for (ours, theirs) in [
+            (Level::Error, ::log::Level::Error),
+            (Level::Warn, ::log::Level::Warn),
+            (Level::Info, ::log::Level::Info),
+            (Level::Debug, ::log::Level::Debug),
+            (Level::Trace, ::log::Level::Trace),
+        ] {
+            assert_eq!(is_enabled(ours), theirs <= max_level());
+        }
+    }
+
+    #[test]
+    fn fmt_macros() {
+        // NOTE there's no logger on the host so these only check that the macros accept
+        // `core::fmt` arguments
+        assert_eq!(
+            error_fmt!("{:?}", core::time::Duration::from_millis(1)),
+            Ok(())
+        );
+        assert_eq!(info_fmt!("{} {:x}", "a", 255u8), Ok(()));
+        assert_eq!(trace_fmt!("no arguments"), Ok(()));
+    }
+
+    #[test]
+    fn read_not_power_of_two() {
+        static INNER: Inner<[u8; 10]> = Inner::new([0; 10]);
+
+        let inner = unsafe { Ring::new(&INNER, None) };
+        let logger = Logger::new(inner);
+        let drain = Drain::new(inner);
+
+        let mut buf = [0; 8];
+        for _ in 0..3 {
+            // NOTE the ring buffer wraps around on the second and third iterations
+            logger.log(b"Hello, w").unwrap();
+            assert_eq!(drain.read(&mut buf[..5]), b"Hello");
+            logger.log(b"orld").unwrap();
+            assert_eq!(drain.read(&mut buf), b", world");
+            assert_eq!(drain.read(&mut buf), b"");
+        }
+    }
+
     #[test]
     fn split_write() {
         const N: usize = 32;
@@ -708,14 +3588,14 @@ mod tests {
         static INNER: Inner<[u8; N]> = Inner::new([0; N]);
 
         let m = "Hello, world!";
-        let inner = &INNER;
+        let inner = unsafe { Ring::new(&INNER, None) };
         unsafe {
             // fake read/write pointers
-            *inner.read.get() = M;
-            *inner.write.get() = M;
+            inner.read.store(M, Ordering::Relaxed);
+            inner.write.store(M, Ordering::Relaxed);
 
-            let logger = Logger { inner };
-            logger.log(m).unwrap();
+            let logger = Logger::new(inner);
+            logger.log(m.as_bytes()).unwrap();
             let m = m.as_bytes();
             let buffer = &*logger.inner.buffer.get();
             assert_eq!(buffer[M..], m[..(N - M)]);
@@ -728,18 +3608,92 @@ mod tests {
         static INNER: Inner<[u8; 32]> = Inner::new([0; 32]);
 
         let m = "Hello, world!";
-        let inner = &INNER;
+        let inner = unsafe { Ring::new(&INNER, None) };
         unsafe {
             // fake read/write pointers
-            *inner.read.get() = usize::max_value();
-            *inner.write.get() = usize::max_value();
+            inner.read.store(usize::MAX, Ordering::Relaxed);
+            inner.write.store(usize::MAX, Ordering::Relaxed);
 
-            let logger = Logger { inner };
-            logger.log(m).unwrap();
+            let logger = Logger::new(inner);
+            logger.log(m.as_bytes()).unwrap();
 
             let buffer = &*logger.inner.buffer.get();
             assert_eq!(buffer.last(), Some(&b'H'));
             assert_eq!(buffer[..m.len() - 1], m.as_bytes()[1..]);
         }
     }
+
+    #[cfg(feature = "basepri")]
+    #[test]
+    fn ceiling_index() {
+        use super::ceiling_index;
+
+        // NVIC_PRIO_BITS = 3; rings for priorities 3 (`0xA0`) and 1 (`0xE0`), highest priority
+        // first; priority 2 (`0xC0`) has no ring
+        let index = |nvic_prio: u8| match nvic_prio {
+            0xA0 => 0,
+            0xE0 => 1,
+            _ => u8::MAX,
+        };
+
+        // BASEPRI not raised
+        assert_eq!(ceiling_index(0, 0xE0, 2, index), None);
+
+        // BASEPRI raised to priority 3 within a priority 1 interrupt
+        assert_eq!(ceiling_index(0xA0, 0xE0, 2, index), Some(0));
+
+        // BASEPRI at or below the priority of the running interrupt doesn't change anything
+        assert_eq!(ceiling_index(0xE0, 0xE0, 2, index), None);
+        assert_eq!(ceiling_index(0xE0, 0xA0, 2, index), None);
+
+        // the ceiling, priority 2, has no ring: the interrupt keeps using its own
+        assert_eq!(ceiling_index(0xC0, 0xE0, 2, index), None);
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn waker() {
+        use core::{
+            ptr,
+            sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+            task::{RawWaker, RawWakerVTable, Waker},
+        };
+
+        use super::{UnsafeCell, WakerSlot};
+
+        static WOKEN: AtomicUsize = AtomicUsize::new(0);
+        static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, wake, wake, drop);
+
+        fn clone(_: *const ()) -> RawWaker {
+            RawWaker::new(ptr::null(), &VTABLE)
+        }
+
+        fn wake(_: *const ()) {
+            WOKEN.fetch_add(1, Ordering::Relaxed);
+        }
+
+        fn drop(_: *const ()) {}
+
+        let slot = WakerSlot {
+            registered: AtomicBool::new(false),
+            waker: UnsafeCell::new(None),
+        };
+        let waker = unsafe { Waker::from_raw(clone(ptr::null())) };
+
+        // nothing registered yet
+        slot.wake();
+        assert_eq!(WOKEN.load(Ordering::Relaxed), 0);
+
+        slot.register(&waker);
+        slot.wake();
+        assert_eq!(WOKEN.load(Ordering::Relaxed), 1);
+
+        // the task must register its waker again to be woken up again
+        slot.wake();
+        assert_eq!(WOKEN.load(Ordering::Relaxed), 1);
+
+        slot.register(&waker);
+        slot.wake();
+        assert_eq!(WOKEN.load(Ordering::Relaxed), 2);
+    }
 }
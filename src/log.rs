@@ -0,0 +1,102 @@
+//! A backend for the `log` crate
+//!
+//! Enabling the "log" Cargo feature provides a `log::Log` implementation that formats each
+//! `Record` into the ring buffer of the caller's priority level, so the `log::info!` & co calls
+//! in third-party crates reach the `funnel` ring buffers. Records logged from a priority level
+//! that has no ring buffer go into the shared ring buffer, if the `shared` option of the `funnel!`
+//! macro was used, or are dropped.
+//!
+//! `init` must be called, once, before any interrupt handler logs.
+//!
+//! ``` ignore
+//! #[entry]
+//! fn main() -> ! {
+//!     let drains = Drains::take().unwrap();
+//!
+//!     funnel::log::init().unwrap();
+//!
+//!     // .. unmask interrupts and drain as usual ..
+//! }
+//!
+//! #[interrupt]
+//! fn GPIOA() {
+//!     // appears in the ring buffer of this interrupt's priority level as "INFO GPIOA\n"
+//!     log::info!("GPIOA");
+//! }
+//! ```
+//!
+//! The logging level features of this crate (e.g. `max_level_info`) also apply to these records.
+
+use core::fmt::Write as _;
+
+use ::log::{LevelFilter, Metadata, Record, SetLoggerError};
+
+use crate::{selected_log_level, Level, Logger};
+
+struct Funnel;
+
+static FUNNEL: Funnel = Funnel;
+
+impl ::log::Log for Funnel {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        if let Some(mut logger) = Logger::get() {
+            write(&mut logger, record);
+        }
+    }
+
+    fn flush(&self) {
+        // NOTE nothing to flush: the application drains the ring buffers (see `Drain::read` for the
+        // contexts it may do that from)
+    }
+}
+
+/// Registers `funnel` as the global logger of the `log` crate
+///
+/// This returns an error if a global logger has already been registered.
+pub fn init() -> Result<(), SetLoggerError> {
+    #[cfg(target_has_atomic = "ptr")]
+    {
+        ::log::set_logger(&FUNNEL)?;
+        ::log::set_max_level(max_level());
+    }
+
+    // ARMv6-M has no compare-and-swap instructions so we use the racy variants with interrupts
+    // masked
+    #[cfg(not(target_has_atomic = "ptr"))]
+    crate::interrupt_free(|| unsafe {
+        ::log::set_logger_racy(&FUNNEL)?;
+        ::log::set_max_level_racy(max_level());
+        Ok::<_, SetLoggerError>(())
+    })?;
+
+    Ok(())
+}
+
+// Formats `record` as "{level} {args}\n", after the exception tag if enabled
+pub(crate) fn write(logger: &mut Logger, record: &Record) {
+    writeln!(crate::tag(logger), "{} {}", record.level(), record.args()).ok();
+}
+
+// The level selected using this crate's Cargo features
+pub(crate) fn max_level() -> LevelFilter {
+    level_filter(selected_log_level())
+}
+
+pub(crate) fn level_filter(level: Option<Level>) -> LevelFilter {
+    match level {
+        None => LevelFilter::Off,
+        Some(Level::Error) => LevelFilter::Error,
+        Some(Level::Warn) => LevelFilter::Warn,
+        Some(Level::Info) => LevelFilter::Info,
+        Some(Level::Debug) => LevelFilter::Debug,
+        Some(Level::Trace) => LevelFilter::Trace,
+    }
+}
@@ -0,0 +1,119 @@
+//! IMPLEMENTATION DETAIL; DO NOT USE
+//!
+//! Checks `Logger::log` and `Drain::read` against a `VecDeque` reference model. Used by the
+//! property tests and by the fuzz target in the `fuzz` directory.
+
+use std::{boxed::Box, collections::VecDeque, vec, vec::Vec};
+
+use crate::{wrap_point, Drain, Inner, Logger, Ring};
+
+/// Buffer sizes the model is checked against
+pub const SIZES: &[usize] = &[1, 2, 3, 5, 7, 8, 10, 16, 31, 32, 64];
+
+/// An operation on a ring buffer
+#[derive(Clone, Copy, Debug)]
+pub enum Op {
+    /// `Logger::log` this many bytes
+    Write(usize),
+    /// `Drain::read` into a buffer of this size
+    Read(usize),
+}
+
+/// Runs `ops` on a ring buffer of `SIZES[size % SIZES.len()]` bytes whose pointers start at
+/// `start`, and on the reference model, and panics if they disagree
+pub fn check(size: usize, start: usize, ops: &[Op]) {
+    let size = SIZES[size % SIZES.len()];
+
+    macro_rules! dispatch {
+        ($($n:expr),*) => {
+            match size {
+                $($n => run(Box::new(Inner::new([0; $n])), start, ops),)*
+                _ => unreachable!(),
+            }
+        };
+    }
+
+    dispatch!(1, 2, 3, 5, 7, 8, 10, 16, 31, 32, 64)
+}
+
+/// Decodes `data` into a `check` call: one byte selects the buffer size, eight bytes the start
+/// pointer and then each pair of bytes is an operation
+pub fn fuzz(data: &[u8]) {
+    if data.len() < 9 {
+        return;
+    }
+
+    let size = usize::from(data[0]);
+    let mut start = [0; 8];
+    start.copy_from_slice(&data[1..9]);
+    let start = u64::from_le_bytes(start) as usize;
+
+    let ops = data[9..]
+        .chunks_exact(2)
+        .map(|op| {
+            let n = usize::from(op[1] & 0x7f);
+            if op[0] & 1 == 0 {
+                Op::Write(n)
+            } else {
+                Op::Read(n)
+            }
+        })
+        .collect::<Vec<_>>();
+
+    check(size, start, &ops);
+}
+
+fn run<const N: usize>(inner: Box<Inner<[u8; N]>>, start: usize, ops: &[Op]) {
+    // only pointers below the wrap point are valid for sizes that are not a power of 2
+    let start = match wrap_point(N) {
+        0 => start,
+        wrap => start % wrap,
+    };
+
+    // NOTE `Logger` and `Drain` need a `'static` reference; this is freed at the end
+    let raw = Box::into_raw(inner);
+    let inner = unsafe { Ring::new(&*raw, None) };
+    inner
+        .read
+        .store(start, core::sync::atomic::Ordering::Relaxed);
+    inner
+        .write
+        .store(start, core::sync::atomic::Ordering::Relaxed);
+
+    let logger = Logger::new(inner);
+    let drain = Drain::new(inner);
+
+    let mut model = VecDeque::new();
+    let mut hwm = 0;
+    let mut next = 0u8;
+    for op in ops {
+        match *op {
+            Op::Write(n) => {
+                let bytes = (0..n)
+                    .map(|_| {
+                        next = next.wrapping_add(1);
+                        next
+                    })
+                    .collect::<Vec<_>>();
+
+                let fits = model.len() + n <= N;
+                assert_eq!(logger.log(&bytes).is_ok(), fits, "{:?}", op);
+
+                if fits {
+                    model.extend(bytes);
+                    hwm = hwm.max(model.len());
+                }
+            }
+
+            Op::Read(n) => {
+                let mut buf = vec![0; n];
+                let expected = model.drain(..n.min(model.len())).collect::<Vec<_>>();
+                assert_eq!(drain.read(&mut buf), &expected[..], "{:?}", op);
+            }
+        }
+
+        assert_eq!(drain.high_water_mark(), hwm);
+    }
+
+    unsafe { drop(Box::from_raw(raw)) }
+}
@@ -0,0 +1,168 @@
+//! A panic handler that flushes the ring buffers before halting or resetting the device
+//!
+//! Linking to this module (by enabling the "panic" Cargo feature) provides the `#[panic_handler]`
+//! so it can't be used together with crates like `panic-halt`.
+//!
+//! On panic, the handler masks all interrupts, formats the `PanicInfo` into a dedicated buffer and
+//! then synchronously drains every ring buffer, highest priority first, followed by the shared ring
+//! buffer and the panic message, through the sink passed to `register`. If no sink has been
+//! registered the data is not flushed.
+//!
+//! ``` ignore
+//! use funnel::panic::{self, Action};
+//!
+//! fn sink(bytes: &[u8]) {
+//!     // .. blocking write to the ITM, UART, etc. ..
+//! }
+//!
+//! #[entry]
+//! fn main() -> ! {
+//!     panic::register(sink, Action::Reset);
+//!
+//!     // ..
+//! }
+//! ```
+
+// the panic handler is not compiled in test mode
+#![cfg_attr(test, allow(dead_code, unused_imports))]
+
+use core::{
+    cell::UnsafeCell,
+    cmp, fmt, mem,
+    panic::PanicInfo,
+    ptr,
+    sync::atomic::{self, AtomicBool, AtomicPtr, AtomicU8, Ordering},
+};
+
+use crate::Drains;
+
+// Cortex-M MMIO registers
+const SCB_AIRCR: *mut u32 = 0xE000_ED0C as *mut u32;
+
+/// Size of the buffer the panic message is formatted into; longer messages are truncated
+pub const MESSAGE_SIZE: usize = 256;
+
+/// What to do after the ring buffers have been flushed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    /// Stop execution
+    Halt,
+    /// Request a system reset
+    Reset,
+}
+
+static SINK: AtomicPtr<()> = AtomicPtr::new(ptr::null_mut());
+static ACTION: AtomicU8 = AtomicU8::new(Action::Halt as u8);
+static PANICKING: AtomicBool = AtomicBool::new(false);
+
+struct Message {
+    buffer: UnsafeCell<[u8; MESSAGE_SIZE]>,
+}
+
+// NOTE only the panic handler accesses this buffer, and only once
+unsafe impl Sync for Message {}
+
+static MESSAGE: Message = Message {
+    buffer: UnsafeCell::new([0; MESSAGE_SIZE]),
+};
+
+/// Registers the blocking `sink` the ring buffers will be flushed through on panic, and the
+/// `action` to take afterwards
+///
+/// The sink will be called with interrupts masked so it must not depend on interrupts to complete
+/// the transfer.
+pub fn register(sink: fn(&[u8]), action: Action) {
+    ACTION.store(action as u8, Ordering::Relaxed);
+    SINK.store(sink as *mut (), Ordering::Release);
+}
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    #[cfg(cortex_m)]
+    unsafe {
+        core::arch::asm!("cpsid i", options(nomem, nostack, preserves_flags));
+    }
+
+    // NOTE if the sink panics we don't try to flush the data again. Interrupts are masked so this
+    // load-store sequence can't be preempted (and ARMv6-M has no atomic swap)
+    if !PANICKING.load(Ordering::Relaxed) {
+        PANICKING.store(true, Ordering::Relaxed);
+
+        let sink = SINK.load(Ordering::Acquire);
+
+        if !sink.is_null() {
+            let sink = unsafe { mem::transmute::<*mut (), fn(&[u8])>(sink) };
+
+            // NOTE this is the only reference to `MESSAGE`, see `PANICKING`
+            let message = format(unsafe { &mut *MESSAGE.buffer.get() }, info);
+
+            flush(sink);
+            sink(message);
+        }
+    }
+
+    if ACTION.load(Ordering::Relaxed) == Action::Reset as u8 && cfg!(cortex_m) {
+        unsafe {
+            // VECTKEY = 0x05FA, SYSRESETREQ = 1
+            atomic::compiler_fence(Ordering::SeqCst);
+            SCB_AIRCR.write_volatile(0x05FA_0004);
+        }
+    }
+
+    loop {
+        atomic::compiler_fence(Ordering::SeqCst);
+    }
+}
+
+// Drains every ring buffer through `sink`
+fn flush(sink: fn(&[u8])) {
+    // NOTE the panic handler never returns to the owner of the drains so stealing them is fine
+    let drains = unsafe { Drains::steal() };
+
+    let mut buf = [0; 64];
+    for drain in drains
+        .hardfault()
+        .into_iter()
+        .chain(drains.iter())
+        .chain(drains.shared())
+    {
+        loop {
+            // NOTE `dequeue` doesn't check that it's called from thread mode
+            let bytes = drain.dequeue(&mut buf);
+
+            if bytes.is_empty() {
+                break;
+            }
+
+            sink(bytes);
+        }
+    }
+}
+
+// Formats `args` into `buffer`, truncating the output if it doesn't fit
+pub(crate) fn format(buffer: &mut [u8], args: impl fmt::Display) -> &[u8] {
+    let mut cursor = Cursor { buffer, pos: 0 };
+    fmt::write(&mut cursor, format_args!("{}\n", args)).ok();
+    let Cursor { buffer, pos } = cursor;
+    &buffer[..pos]
+}
+
+struct Cursor<'b> {
+    buffer: &'b mut [u8],
+    pos: usize,
+}
+
+impl fmt::Write for Cursor<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let n = cmp::min(s.len(), self.buffer.len() - self.pos);
+        self.buffer[self.pos..self.pos + n].copy_from_slice(&s.as_bytes()[..n]);
+        self.pos += n;
+
+        if n == s.len() {
+            Ok(())
+        } else {
+            Err(fmt::Error)
+        }
+    }
+}